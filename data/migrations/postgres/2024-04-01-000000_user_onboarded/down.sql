@@ -0,0 +1 @@
+ALTER TABLE youtube_users DROP COLUMN onboarded;
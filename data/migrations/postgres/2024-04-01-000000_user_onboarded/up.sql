@@ -0,0 +1,3 @@
+ALTER TABLE youtube_users ADD COLUMN onboarded BOOLEAN NOT NULL DEFAULT FALSE;
+-- channels added before this migration have already been backfilled
+UPDATE youtube_users SET onboarded = TRUE;
@@ -0,0 +1,2 @@
+-- NIP-46 remote signer of the channel; its privatekey is then only the client key
+ALTER TABLE youtube_users ADD COLUMN bunker VARCHAR NULL;
@@ -0,0 +1,2 @@
+-- relays of the last NIP-65 relay list published for the channel's identity
+ALTER TABLE youtube_users ADD COLUMN relay_list VARCHAR NULL;
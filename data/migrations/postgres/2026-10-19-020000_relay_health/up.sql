@@ -0,0 +1,15 @@
+-- outcome of the events sent to each relay, to demote relays that keep failing
+CREATE TABLE relay_health (
+    url VARCHAR PRIMARY KEY NOT NULL,
+    sent INTEGER NOT NULL DEFAULT 0,
+    accepted INTEGER NOT NULL DEFAULT 0,
+    rejected INTEGER NOT NULL DEFAULT 0,
+    -- not reachable or no answer in time
+    failed INTEGER NOT NULL DEFAULT 0,
+    consecutive_failures INTEGER NOT NULL DEFAULT 0,
+    -- sum over the accepted events
+    latency_ms BIGINT NOT NULL DEFAULT 0,
+    last_error VARCHAR NULL,
+    -- unix time of the last event sent
+    last_attempt BIGINT NOT NULL DEFAULT 0
+);
@@ -0,0 +1,9 @@
+CREATE TABLE youtube_users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    username VARCHAR NOT NULL,
+    avatar VARCHAR  NULL,
+    publickey VARCHAR NOT NULL,
+    privatekey VARCHAR NOT NULL,
+    channel VARCHAR NOT NULL,
+    channel_id VARCHAR NOT NULL
+)
@@ -0,0 +1,9 @@
+CREATE TABLE videos (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    author VARCHAR NOT NULL,
+    channel VARCHAR NOT NULL,
+    title VARCHAR NOT NULL,
+    link VARCHAR NOT NULL,
+    published BOOLEAN NOT NULL DEFAULT FALSE,
+    userid INTEGER REFERENCES youtube_users(id)  NOT NULL
+)
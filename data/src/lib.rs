@@ -1,13 +1,40 @@
+mod memory;
+mod metrics;
 mod models;
 mod schema;
 
+pub use memory::MemoryStore;
+pub use metrics::query_duration;
+pub use models::{RelayHealth, RelayOutcome, DEMOTED_RETRY, DEMOTE_AFTER};
+
+use crate::metrics::timed;
 use crate::models::{NewVideos, NewYoutubeUser, Videos, YoutubeUser};
 
+use diesel::connection::SimpleConnection;
+use diesel::upsert::excluded;
 use diesel::RunQueryDsl;
-use diesel::{Connection, ExpressionMethods, PgConnection, QueryDsl};
+use diesel::SelectableHelper;
+use diesel::SqliteConnection;
+use diesel::{Connection, ConnectionError, ExpressionMethods, PgConnection, QueryDsl};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-
-pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("../data/migrations");
+use std::future::Future;
+
+pub const POSTGRES_MIGRATIONS: EmbeddedMigrations =
+    embed_migrations!("../data/migrations/postgres");
+/// SQLite versions of the Postgres migrations, under the same versions;
+/// migrations whose SQL works on both are symlinks to the Postgres ones.
+pub const SQLITE_MIGRATIONS: EmbeddedMigrations = embed_migrations!("../data/migrations/sqlite");
+
+/// DSN prefix selecting the SQLite backend, followed by the database file
+/// path, e.g. `sqlite://ytbot.db`.
+pub const SQLITE_SCHEME: &str = "sqlite://";
+
+/// Connection to either backend; queries are written once for both.
+#[derive(diesel::MultiConnection)]
+pub enum AnyConnection {
+    Postgresql(PgConnection),
+    Sqlite(SqliteConnection),
+}
 
 #[derive(Debug)]
 pub enum Error {
@@ -15,6 +42,9 @@ pub enum Error {
     Connection(diesel::ConnectionError),
     IoError(std::io::Error),
     SerdeError(serde_yaml::Error),
+    /// No user row exists for the channel.
+    UserNotFound(String),
+    Migration(Box<dyn std::error::Error + Send + Sync>),
 }
 
 impl From<diesel::ConnectionError> for Error {
@@ -48,50 +78,262 @@ impl std::fmt::Display for Error {
             Error::Connection(err) => write!(f, "Connection error: {}", err),
             Error::IoError(err) => write!(f, "IO error: {}", err),
             Error::SerdeError(err) => write!(f, "Serde error: {}", err),
+            Error::UserNotFound(ch) => write!(f, "User not found for channel {}", ch),
+            Error::Migration(err) => write!(f, "Migration error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Diesel(err) => Some(err),
+            Error::Connection(err) => Some(err),
+            Error::IoError(err) => Some(err),
+            Error::SerdeError(err) => Some(err),
+            Error::UserNotFound(_) => None,
+            Error::Migration(err) => Some(err.as_ref()),
+        }
+    }
+}
+
+impl Error {
+    /// Lost or refused connections and conflicts that a retry may resolve.
+    pub fn is_transient(&self) -> bool {
+        use diesel::result::DatabaseErrorKind;
+        match self {
+            Error::Connection(ConnectionError::InvalidConnectionUrl(_)) => false,
+            Error::Connection(_) | Error::IoError(_) => true,
+            Error::Diesel(diesel::result::Error::DatabaseError(kind, _)) => matches!(
+                kind,
+                DatabaseErrorKind::ClosedConnection | DatabaseErrorKind::SerializationFailure
+            ),
+            _ => false,
         }
     }
 }
 
+/// The users and videos the bot keeps between polls. Users are looked up by
+/// channel name and videos by link.
+pub trait Store: Send {
+    fn query_channel_id(
+        &mut self,
+        ch: &str,
+    ) -> impl Future<Output = Result<Option<String>, Error>> + Send;
+
+    fn video_exists(&mut self, lk: &str) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    fn user_exists(&mut self, ch: &str) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Names of the channels that have a user, sorted.
+    fn channels(&mut self) -> impl Future<Output = Result<Vec<String>, Error>> + Send;
+
+    fn query_avatar(
+        &mut self,
+        ch: &str,
+    ) -> impl Future<Output = Result<Option<String>, Error>> + Send;
+
+    fn query_user_name(
+        &mut self,
+        ch: &str,
+    ) -> impl Future<Output = Result<Option<String>, Error>> + Send;
+
+    /// Adds the user of channel `ch` with its bech32 public and secret keys.
+    fn add_user(
+        &mut self,
+        un: &str,
+        av: &str,
+        pk: &str,
+        prk: &str,
+        ch: &str,
+        chid: &str,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Records a video of channel `ch`, failing with [`Error::UserNotFound`]
+    /// if the channel has no user.
+    fn add_video(
+        &mut self,
+        au: &str,
+        ch: &str,
+        ti: &str,
+        lk: &str,
+        pu: bool,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    fn remove_video(&mut self, lk: &str) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Whether a note announcing the video at `lk` was published.
+    fn video_published(&mut self, lk: &str) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Records that a note announcing the video at `lk` was published. A
+    /// video that was not recorded is left alone.
+    fn set_published(&mut self, lk: &str) -> impl Future<Output = Result<(), Error>> + Send;
+
+    fn user_onboarded(&mut self, ch: &str) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    fn set_onboarded(&mut self, ch: &str) -> impl Future<Output = Result<(), Error>> + Send;
+
+    fn find_user_private_key(
+        &mut self,
+        ch: &str,
+    ) -> impl Future<Output = Result<Option<String>, Error>> + Send;
+
+    fn find_user_public_key(
+        &mut self,
+        ch: &str,
+    ) -> impl Future<Output = Result<Option<String>, Error>> + Send;
+
+    /// The channel whose identity is the public key `pk`, if any.
+    fn find_channel_by_key(
+        &mut self,
+        pk: &str,
+    ) -> impl Future<Output = Result<Option<String>, Error>> + Send;
+
+    /// Replaces the keys of channel `ch`, failing with
+    /// [`Error::UserNotFound`] if the channel has no user.
+    fn set_keys(
+        &mut self,
+        ch: &str,
+        pk: &str,
+        prk: &str,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// The NIP-46 bunker URI of channel `ch`, if it signs remotely.
+    fn find_user_bunker(
+        &mut self,
+        ch: &str,
+    ) -> impl Future<Output = Result<Option<String>, Error>> + Send;
+
+    /// Sets or clears the bunker URI of channel `ch`, failing with
+    /// [`Error::UserNotFound`] if the channel has no user.
+    fn set_bunker(
+        &mut self,
+        ch: &str,
+        uri: Option<&str>,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// The relays of the last NIP-65 relay list published for channel `ch`,
+    /// as recorded by [`Store::set_relay_list`].
+    fn find_user_relay_list(
+        &mut self,
+        ch: &str,
+    ) -> impl Future<Output = Result<Option<String>, Error>> + Send;
+
+    /// Records or clears the published relay list of channel `ch`, failing
+    /// with [`Error::UserNotFound`] if the channel has no user.
+    fn set_relay_list(
+        &mut self,
+        ch: &str,
+        relays: Option<&str>,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Records how relay `url` answered an event sent at `at`, in unix
+    /// seconds.
+    fn record_relay_send(
+        &mut self,
+        url: &str,
+        outcome: &RelayOutcome,
+        at: i64,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// The health of every relay events were sent to, sorted by URL.
+    fn relay_health(&mut self) -> impl Future<Output = Result<Vec<RelayHealth>, Error>> + Send;
+}
+
+/// Executes `$query` on the concrete connection. Inserts of `Insertable`
+/// structs need the backend's own dialect, which the multi-backend
+/// connection does not provide.
+macro_rules! execute_on_backend {
+    ($conn:expr, $query:expr) => {
+        match $conn {
+            AnyConnection::Postgresql(conn) => $query.execute(conn),
+            AnyConnection::Sqlite(conn) => $query.execute(conn),
+        }
+    };
+}
+
 pub struct DbConnection {
-    conn: PgConnection,
+    conn: AnyConnection,
 }
 
 impl DbConnection {
+    /// Connects to SQLite for `sqlite://` DSNs and to Postgres otherwise.
     pub fn new(dsn: &str) -> Result<DbConnection, Error> {
-        let conn = PgConnection::establish(dsn)?;
+        let conn = match dsn.strip_prefix(SQLITE_SCHEME) {
+            Some(path) => {
+                let mut conn = SqliteConnection::establish(path)?;
+                // every channel has its own connection, so wait for locks
+                // instead of failing
+                conn.batch_execute(
+                    "PRAGMA busy_timeout = 5000; PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;",
+                )?;
+                AnyConnection::Sqlite(conn)
+            }
+            None => AnyConnection::Postgresql(PgConnection::establish(dsn)?),
+        };
         Ok(DbConnection { conn })
     }
 
-    pub fn run_migrations(
-        &mut self,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
-        self.conn.run_pending_migrations(MIGRATIONS)?;
+    pub fn run_migrations(&mut self) -> Result<(), Error> {
+        match &mut self.conn {
+            AnyConnection::Postgresql(conn) => conn.run_pending_migrations(POSTGRES_MIGRATIONS),
+            AnyConnection::Sqlite(conn) => conn.run_pending_migrations(SQLITE_MIGRATIONS),
+        }
+        .map_err(Error::Migration)?;
+        Ok(())
+    }
+
+    /// Checks that the database answers queries.
+    pub fn ping(&mut self) -> Result<(), Error> {
+        timed("ping", || {
+            diesel::sql_query("SELECT 1").execute(&mut self.conn)
+        })?;
         Ok(())
     }
 
+    /// The public key of every channel, as `(channel, publickey)` pairs.
+    pub fn public_keys(&mut self) -> Result<Vec<(String, String)>, Error> {
+        use crate::schema::youtube_users::dsl::*;
+        Ok(timed("public_keys", || {
+            youtube_users
+                .select((channel, publickey))
+                .load::<(String, String)>(&mut self.conn)
+        })?)
+    }
+
+    /// The health of every relay events were sent to, sorted by URL.
+    pub fn load_relay_health(&mut self) -> Result<Vec<RelayHealth>, Error> {
+        use crate::schema::relay_health::dsl::*;
+        Ok(timed("relay_health", || {
+            relay_health
+                .order(url.asc())
+                .select(RelayHealth::as_select())
+                .load(&mut self.conn)
+        })?)
+    }
+
     fn load_users(&mut self, ch: &str) -> Result<Vec<YoutubeUser>, Error> {
         use crate::schema::youtube_users::dsl::*;
-        Ok(youtube_users
-            .filter(channel.eq(ch))
-            .load::<YoutubeUser>(&mut self.conn)?)
+        Ok(timed("load_users", || {
+            youtube_users
+                .filter(channel.eq(ch))
+                .load::<YoutubeUser>(&mut self.conn)
+        })?)
     }
 
     pub fn add_avatar(&mut self, ch: &str, av: &str) -> Result<(), Error> {
         use crate::schema::youtube_users::dsl::*;
 
-        Ok(diesel::update(youtube_users.filter(channel.eq(ch)))
-            .set(avatar.eq(av))
-            .execute(&mut self.conn)
-            .map_err(|err| {
-                log::error!("Error adding avatar: {}", err);
-                err
-            })
-            .map(|_| ())?)
-    }
-
-    pub async fn query_channel_id(&mut self, name: &str) -> Result<Option<String>, Error> {
-        let results = self.load_users(name)?;
-        Ok(results.first().map(|user| user.channel_id.to_string()))
+        Ok(timed("add_avatar", || {
+            diesel::update(youtube_users.filter(channel.eq(ch)))
+                .set(avatar.eq(av))
+                .execute(&mut self.conn)
+        })
+        .map_err(|err| {
+            log::error!("Error adding avatar: {}", err);
+            err
+        })
+        .map(|_| ())?)
     }
 
     pub async fn avatar_exists(&mut self, name: &str) -> Result<Option<String>, Error> {
@@ -99,33 +341,60 @@ impl DbConnection {
         Ok(results.first().and_then(|user| user.avatar.clone()))
     }
 
-    pub async fn video_exists(&mut self, lk: &str) -> Result<bool, Error> {
-        use crate::schema::videos::dsl::*;
-        let results = videos.filter(link.eq(lk)).load::<Videos>(&mut self.conn)?;
+    pub async fn channel_exists(&mut self, ch: &str) -> Result<bool, Error> {
+        let results = self.load_users(ch)?;
         Ok(!results.is_empty())
     }
 
-    pub async fn channel_exists(&mut self, ch: &str) -> Result<bool, Error> {
+    pub async fn query_user_id(&mut self, ch: &str) -> Result<Option<i32>, Error> {
         let results = self.load_users(ch)?;
+        Ok(results.first().map(|user| user.id))
+    }
+}
+
+impl Store for DbConnection {
+    async fn query_channel_id(&mut self, name: &str) -> Result<Option<String>, Error> {
+        let results = self.load_users(name)?;
+        Ok(results.first().map(|user| user.channel_id.to_string()))
+    }
+
+    async fn video_exists(&mut self, lk: &str) -> Result<bool, Error> {
+        use crate::schema::videos::dsl::*;
+        let results = timed("video_exists", || {
+            videos
+                .filter(link.eq(lk))
+                .select(Videos::as_select())
+                .load(&mut self.conn)
+        })?;
         Ok(!results.is_empty())
     }
 
-    pub async fn user_exists(&mut self, name: &str) -> Result<bool, Error> {
+    async fn user_exists(&mut self, name: &str) -> Result<bool, Error> {
         let results = self.load_users(name)?;
         Ok(!results.is_empty())
     }
 
-    pub async fn query_avatar(&mut self, name: &str) -> Result<Option<String>, Error> {
+    async fn channels(&mut self) -> Result<Vec<String>, Error> {
+        use crate::schema::youtube_users::dsl::*;
+        Ok(timed("channels", || {
+            youtube_users
+                .select(channel)
+                .order(channel.asc())
+                .load::<String>(&mut self.conn)
+        })?)
+    }
+
+    async fn query_avatar(&mut self, name: &str) -> Result<Option<String>, Error> {
         let results = self.load_users(name)?;
         Ok(results.first().and_then(|user| user.avatar.clone()))
     }
 
-    pub async fn query_user_name(&mut self, ch: &str) -> Result<Option<String>, Error> {
+    async fn query_user_name(&mut self, ch: &str) -> Result<Option<String>, Error> {
         let results = self.load_users(ch)?;
         Ok(results.first().map(|user| user.username.to_string()))
     }
 
-    pub async fn add_user(
+    async fn add_user(
         &mut self,
         un: &str,
         av: &str,
@@ -145,22 +414,20 @@ impl DbConnection {
             channel_id: chid.to_string(),
         };
 
-        Ok(diesel::insert_into(youtube_users)
-            .values(&new_user)
-            .execute(&mut self.conn)
-            .map_err(|err| {
-                log::error!("Error adding user: {}", err);
-                err
-            })
-            .map(|_| ())?)
-    }
-
-    pub async fn query_user_id(&mut self, ch: &str) -> Result<Option<i32>, Error> {
-        let results = self.load_users(ch)?;
-        Ok(results.first().map(|user| user.id))
+        Ok(timed("add_user", || {
+            execute_on_backend!(
+                &mut self.conn,
+                diesel::insert_into(youtube_users).values(&new_user)
+            )
+        })
+        .map_err(|err| {
+            log::error!("Error adding user: {}", err);
+            err
+        })
+        .map(|_| ())?)
     }
 
-    pub async fn add_video(
+    async fn add_video(
         &mut self,
         au: &str,
         ch: &str,
@@ -170,7 +437,10 @@ impl DbConnection {
     ) -> Result<(), Error> {
         use crate::schema::videos::dsl::*;
 
-        let u_id = self.query_user_id(ch).await?.unwrap();
+        let u_id = self
+            .query_user_id(ch)
+            .await?
+            .ok_or_else(|| Error::UserNotFound(ch.to_string()))?;
 
         let new_video = NewVideos {
             author: au.to_string(),
@@ -181,23 +451,237 @@ impl DbConnection {
             userid: u_id,
         };
 
-        Ok(diesel::insert_into(videos)
-            .values(&new_video)
-            .execute(&mut self.conn)
-            .map_err(|err| {
-                log::error!("Error adding video: {}", err);
-                err
-            })
-            .map(|_| ())?)
+        Ok(timed("add_video", || {
+            execute_on_backend!(
+                &mut self.conn,
+                diesel::insert_into(videos).values(&new_video)
+            )
+        })
+        .map_err(|err| {
+            log::error!("Error adding video: {}", err);
+            err
+        })
+        .map(|_| ())?)
+    }
+
+    async fn remove_video(&mut self, lk: &str) -> Result<(), Error> {
+        use crate::schema::videos::dsl::*;
+
+        Ok(timed("remove_video", || {
+            diesel::delete(videos.filter(link.eq(lk))).execute(&mut self.conn)
+        })
+        .map_err(|err| {
+            log::error!("Error removing video: {}", err);
+            err
+        })
+        .map(|_| ())?)
+    }
+
+    async fn video_published(&mut self, lk: &str) -> Result<bool, Error> {
+        use crate::schema::videos::dsl::*;
+
+        let results = timed("video_published", || {
+            videos
+                .filter(link.eq(lk))
+                .filter(published.eq(true))
+                .select(Videos::as_select())
+                .load(&mut self.conn)
+        })?;
+        Ok(!results.is_empty())
     }
 
-    pub async fn find_user_private_key(&mut self, ch: &str) -> Result<Option<String>, Error> {
+    async fn set_published(&mut self, lk: &str) -> Result<(), Error> {
+        use crate::schema::videos::dsl::*;
+
+        Ok(timed("set_published", || {
+            diesel::update(videos.filter(link.eq(lk)))
+                .set(published.eq(true))
+                .execute(&mut self.conn)
+        })
+        .map_err(|err| {
+            log::error!("Error setting video published: {}", err);
+            err
+        })
+        .map(|_| ())?)
+    }
+
+    async fn user_onboarded(&mut self, ch: &str) -> Result<bool, Error> {
+        let results = self.load_users(ch)?;
+        Ok(results.first().is_some_and(|user| user.onboarded))
+    }
+
+    async fn set_onboarded(&mut self, ch: &str) -> Result<(), Error> {
+        use crate::schema::youtube_users::dsl::*;
+
+        Ok(timed("set_onboarded", || {
+            diesel::update(youtube_users.filter(channel.eq(ch)))
+                .set(onboarded.eq(true))
+                .execute(&mut self.conn)
+        })
+        .map_err(|err| {
+            log::error!("Error setting onboarded: {}", err);
+            err
+        })
+        .map(|_| ())?)
+    }
+
+    async fn find_user_private_key(&mut self, ch: &str) -> Result<Option<String>, Error> {
         let results = self.load_users(ch)?;
         Ok(results.first().map(|user| user.privatekey.to_string()))
     }
 
-    pub async fn find_user_public_key(&mut self, ch: &str) -> Result<Option<String>, Error> {
+    async fn find_user_public_key(&mut self, ch: &str) -> Result<Option<String>, Error> {
         let results = self.load_users(ch)?;
         Ok(results.first().map(|user| user.publickey.to_string()))
     }
+
+    async fn find_channel_by_key(&mut self, pk: &str) -> Result<Option<String>, Error> {
+        use crate::schema::youtube_users::dsl::*;
+        let results = timed("find_channel_by_key", || {
+            youtube_users
+                .filter(publickey.eq(pk))
+                .select(channel)
+                .load::<String>(&mut self.conn)
+        })?;
+        Ok(results.into_iter().next())
+    }
+
+    async fn set_keys(&mut self, ch: &str, pk: &str, prk: &str) -> Result<(), Error> {
+        use crate::schema::youtube_users::dsl::*;
+
+        let updated = timed("set_keys", || {
+            diesel::update(youtube_users.filter(channel.eq(ch)))
+                .set((publickey.eq(pk), privatekey.eq(prk)))
+                .execute(&mut self.conn)
+        })
+        .map_err(|err| {
+            log::error!("Error setting keys: {}", err);
+            err
+        })?;
+        if updated == 0 {
+            return Err(Error::UserNotFound(ch.to_string()));
+        }
+        Ok(())
+    }
+
+    async fn find_user_bunker(&mut self, ch: &str) -> Result<Option<String>, Error> {
+        let results = self.load_users(ch)?;
+        Ok(results.first().and_then(|user| user.bunker.clone()))
+    }
+
+    async fn set_bunker(&mut self, ch: &str, uri: Option<&str>) -> Result<(), Error> {
+        use crate::schema::youtube_users::dsl::*;
+
+        let updated = timed("set_bunker", || {
+            diesel::update(youtube_users.filter(channel.eq(ch)))
+                .set(bunker.eq(uri))
+                .execute(&mut self.conn)
+        })
+        .map_err(|err| {
+            log::error!("Error setting bunker: {}", err);
+            err
+        })?;
+        if updated == 0 {
+            return Err(Error::UserNotFound(ch.to_string()));
+        }
+        Ok(())
+    }
+
+    async fn find_user_relay_list(&mut self, ch: &str) -> Result<Option<String>, Error> {
+        let results = self.load_users(ch)?;
+        Ok(results.first().and_then(|user| user.relay_list.clone()))
+    }
+
+    async fn set_relay_list(&mut self, ch: &str, relays: Option<&str>) -> Result<(), Error> {
+        use crate::schema::youtube_users::dsl::*;
+
+        let updated = timed("set_relay_list", || {
+            diesel::update(youtube_users.filter(channel.eq(ch)))
+                .set(relay_list.eq(relays))
+                .execute(&mut self.conn)
+        })
+        .map_err(|err| {
+            log::error!("Error setting relay list: {}", err);
+            err
+        })?;
+        if updated == 0 {
+            return Err(Error::UserNotFound(ch.to_string()));
+        }
+        Ok(())
+    }
+
+    async fn record_relay_send(
+        &mut self,
+        relay: &str,
+        outcome: &RelayOutcome,
+        at: i64,
+    ) -> Result<(), Error> {
+        use crate::schema::relay_health::dsl::*;
+
+        // the outcome is added to the stored counts in one statement, so
+        // that workers sending to the same relay do not lose each other's
+        let mut health = RelayHealth::new(relay);
+        health.record(outcome, at);
+        let upsert = diesel::insert_into(relay_health)
+            .values(&health)
+            .on_conflict(url)
+            .do_update();
+        let counts = (
+            sent.eq(sent + excluded(sent)),
+            accepted.eq(accepted + excluded(accepted)),
+            rejected.eq(rejected + excluded(rejected)),
+            failed.eq(failed + excluded(failed)),
+            latency_ms.eq(latency_ms + excluded(latency_ms)),
+            last_attempt.eq(excluded(last_attempt)),
+        );
+        Ok(timed("record_relay_send", || match outcome {
+            RelayOutcome::Accepted(_) => execute_on_backend!(
+                &mut self.conn,
+                upsert.set((counts, consecutive_failures.eq(0)))
+            ),
+            RelayOutcome::Rejected(_) | RelayOutcome::Failed(_) => execute_on_backend!(
+                &mut self.conn,
+                upsert.set((
+                    counts,
+                    consecutive_failures.eq(consecutive_failures + 1),
+                    last_error.eq(excluded(last_error)),
+                ))
+            ),
+        })
+        .map_err(|err| {
+            log::error!("Error recording relay send: {}", err);
+            err
+        })?)
+        .map(|_| ())
+    }
+
+    async fn relay_health(&mut self) -> Result<Vec<RelayHealth>, Error> {
+        self.load_relay_health()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::result::DatabaseErrorKind;
+
+    fn database_error(kind: DatabaseErrorKind) -> Error {
+        Error::Diesel(diesel::result::Error::DatabaseError(
+            kind,
+            Box::new(String::from("message")),
+        ))
+    }
+
+    #[test]
+    fn lost_connections_are_transient() {
+        let bad = ConnectionError::BadConnection("connection refused".to_string());
+        assert!(Error::Connection(bad).is_transient());
+        assert!(database_error(DatabaseErrorKind::ClosedConnection).is_transient());
+        assert!(database_error(DatabaseErrorKind::SerializationFailure).is_transient());
+
+        let url = ConnectionError::InvalidConnectionUrl("no scheme".to_string());
+        assert!(!Error::Connection(url).is_transient());
+        assert!(!database_error(DatabaseErrorKind::UniqueViolation).is_transient());
+        assert!(!Error::UserNotFound("channel".to_string()).is_transient());
+    }
 }
@@ -0,0 +1,254 @@
+use crate::models::{RelayHealth, RelayOutcome, Videos, YoutubeUser};
+use crate::{Error, Store};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+#[derive(Default)]
+struct Tables {
+    youtube_users: Vec<YoutubeUser>,
+    videos: Vec<Videos>,
+    relay_health: Vec<RelayHealth>,
+}
+
+/// A [`Store`] kept in memory, for tests and trying the bot out. Clones
+/// share their data, like connections to the same database.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    tables: Arc<Mutex<Tables>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn find_user<T>(&self, ch: &str, f: impl FnOnce(&YoutubeUser) -> T) -> Option<T> {
+        self.tables()
+            .youtube_users
+            .iter()
+            .find(|user| user.channel == ch)
+            .map(f)
+    }
+
+    /// Links of the videos recorded for channel `ch`, in insertion order.
+    pub fn videos(&self, ch: &str) -> Vec<String> {
+        self.tables()
+            .videos
+            .iter()
+            .filter(|video| video.channel == ch)
+            .map(|video| video.link.clone())
+            .collect()
+    }
+}
+
+impl Store for MemoryStore {
+    async fn query_channel_id(&mut self, ch: &str) -> Result<Option<String>, Error> {
+        Ok(self.find_user(ch, |user| user.channel_id.clone()))
+    }
+
+    async fn video_exists(&mut self, lk: &str) -> Result<bool, Error> {
+        Ok(self.tables().videos.iter().any(|video| video.link == lk))
+    }
+
+    async fn user_exists(&mut self, ch: &str) -> Result<bool, Error> {
+        Ok(self.find_user(ch, |_| ()).is_some())
+    }
+
+    async fn channels(&mut self) -> Result<Vec<String>, Error> {
+        let mut channels: Vec<String> = self
+            .tables()
+            .youtube_users
+            .iter()
+            .map(|user| user.channel.clone())
+            .collect();
+        channels.sort();
+        Ok(channels)
+    }
+
+    async fn query_avatar(&mut self, ch: &str) -> Result<Option<String>, Error> {
+        Ok(self.find_user(ch, |user| user.avatar.clone()).flatten())
+    }
+
+    async fn query_user_name(&mut self, ch: &str) -> Result<Option<String>, Error> {
+        Ok(self.find_user(ch, |user| user.username.clone()))
+    }
+
+    async fn add_user(
+        &mut self,
+        un: &str,
+        av: &str,
+        pk: &str,
+        prk: &str,
+        ch: &str,
+        chid: &str,
+    ) -> Result<(), Error> {
+        let mut tables = self.tables();
+        let id = tables.youtube_users.len() as i32 + 1;
+        tables.youtube_users.push(YoutubeUser {
+            id,
+            username: un.to_string(),
+            avatar: Some(av.to_string()),
+            publickey: pk.to_string(),
+            privatekey: prk.to_string(),
+            channel: ch.to_string(),
+            channel_id: chid.to_string(),
+            onboarded: false,
+            bunker: None,
+            relay_list: None,
+        });
+        Ok(())
+    }
+
+    async fn add_video(
+        &mut self,
+        _au: &str,
+        ch: &str,
+        _ti: &str,
+        lk: &str,
+        pu: bool,
+    ) -> Result<(), Error> {
+        if !self.user_exists(ch).await? {
+            return Err(Error::UserNotFound(ch.to_string()));
+        }
+        let mut tables = self.tables();
+        let id = tables
+            .videos
+            .iter()
+            .map(|video| video.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        tables.videos.push(Videos {
+            id,
+            channel: ch.to_string(),
+            link: lk.to_string(),
+            published: pu,
+        });
+        Ok(())
+    }
+
+    async fn remove_video(&mut self, lk: &str) -> Result<(), Error> {
+        self.tables().videos.retain(|video| video.link != lk);
+        Ok(())
+    }
+
+    async fn video_published(&mut self, lk: &str) -> Result<bool, Error> {
+        Ok(self
+            .tables()
+            .videos
+            .iter()
+            .any(|video| video.link == lk && video.published))
+    }
+
+    async fn set_published(&mut self, lk: &str) -> Result<(), Error> {
+        for video in &mut self.tables().videos {
+            if video.link == lk {
+                video.published = true;
+            }
+        }
+        Ok(())
+    }
+
+    async fn user_onboarded(&mut self, ch: &str) -> Result<bool, Error> {
+        Ok(self.find_user(ch, |user| user.onboarded).unwrap_or(false))
+    }
+
+    async fn set_onboarded(&mut self, ch: &str) -> Result<(), Error> {
+        for user in &mut self.tables().youtube_users {
+            if user.channel == ch {
+                user.onboarded = true;
+            }
+        }
+        Ok(())
+    }
+
+    async fn find_user_private_key(&mut self, ch: &str) -> Result<Option<String>, Error> {
+        Ok(self.find_user(ch, |user| user.privatekey.clone()))
+    }
+
+    async fn find_user_public_key(&mut self, ch: &str) -> Result<Option<String>, Error> {
+        Ok(self.find_user(ch, |user| user.publickey.clone()))
+    }
+
+    async fn find_channel_by_key(&mut self, pk: &str) -> Result<Option<String>, Error> {
+        Ok(self
+            .tables()
+            .youtube_users
+            .iter()
+            .find(|user| user.publickey == pk)
+            .map(|user| user.channel.clone()))
+    }
+
+    async fn set_keys(&mut self, ch: &str, pk: &str, prk: &str) -> Result<(), Error> {
+        let mut tables = self.tables();
+        let user = tables
+            .youtube_users
+            .iter_mut()
+            .find(|user| user.channel == ch)
+            .ok_or_else(|| Error::UserNotFound(ch.to_string()))?;
+        user.publickey = pk.to_string();
+        user.privatekey = prk.to_string();
+        Ok(())
+    }
+
+    async fn find_user_bunker(&mut self, ch: &str) -> Result<Option<String>, Error> {
+        Ok(self.find_user(ch, |user| user.bunker.clone()).flatten())
+    }
+
+    async fn set_bunker(&mut self, ch: &str, uri: Option<&str>) -> Result<(), Error> {
+        let mut tables = self.tables();
+        let user = tables
+            .youtube_users
+            .iter_mut()
+            .find(|user| user.channel == ch)
+            .ok_or_else(|| Error::UserNotFound(ch.to_string()))?;
+        user.bunker = uri.map(str::to_string);
+        Ok(())
+    }
+
+    async fn find_user_relay_list(&mut self, ch: &str) -> Result<Option<String>, Error> {
+        Ok(self.find_user(ch, |user| user.relay_list.clone()).flatten())
+    }
+
+    async fn set_relay_list(&mut self, ch: &str, relays: Option<&str>) -> Result<(), Error> {
+        let mut tables = self.tables();
+        let user = tables
+            .youtube_users
+            .iter_mut()
+            .find(|user| user.channel == ch)
+            .ok_or_else(|| Error::UserNotFound(ch.to_string()))?;
+        user.relay_list = relays.map(str::to_string);
+        Ok(())
+    }
+
+    async fn record_relay_send(
+        &mut self,
+        url: &str,
+        outcome: &RelayOutcome,
+        at: i64,
+    ) -> Result<(), Error> {
+        let mut tables = self.tables();
+        let index = match tables
+            .relay_health
+            .iter()
+            .position(|relay| relay.url == url)
+        {
+            Some(index) => index,
+            None => {
+                tables.relay_health.push(RelayHealth::new(url));
+                tables.relay_health.len() - 1
+            }
+        };
+        tables.relay_health[index].record(outcome, at);
+        Ok(())
+    }
+
+    async fn relay_health(&mut self) -> Result<Vec<RelayHealth>, Error> {
+        let mut relays = self.tables().relay_health.clone();
+        relays.sort_by(|a, b| a.url.cmp(&b.url));
+        Ok(relays)
+    }
+}
@@ -0,0 +1,39 @@
+use prometheus::{register_histogram_vec, HistogramVec};
+use std::sync::OnceLock;
+use std::time::Instant;
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+static QUERY_DURATION: OnceLock<HistogramVec> = OnceLock::new();
+
+/// Histogram of database query latency by query name, registered in the
+/// default Prometheus registry on first use.
+pub fn query_duration() -> &'static HistogramVec {
+    QUERY_DURATION.get_or_init(|| {
+        register_histogram_vec!(
+            "ytbot_db_query_duration_seconds",
+            "Database query latency.",
+            &["query"]
+        )
+        .expect("metric is registered once")
+    })
+}
+
+/// Runs `f`, recording its duration as the latency of `query`.
+pub(crate) fn timed<T>(query: &str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let ret = blocking(f);
+    query_duration()
+        .with_label_values(&[query])
+        .observe(start.elapsed().as_secs_f64());
+    ret
+}
+
+/// Runs the blocking `f`. On a multi-threaded runtime the worker thread
+/// first hands its other tasks to another thread, so that a slow query or a
+/// wait for a SQLite lock does not stall them.
+fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    match Handle::try_current().map(|handle| handle.runtime_flavor()) {
+        Ok(RuntimeFlavor::MultiThread) => tokio::task::block_in_place(f),
+        _ => f(),
+    }
+}
@@ -1,17 +1,14 @@
 use diesel::prelude::*;
-use sonic_rs::Deserialize;
+use std::time::Duration;
 
 #[derive(Queryable, Selectable)]
 #[diesel(table_name = crate::schema::videos)]
-#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
 pub struct Videos {
     pub id: i32,
-    pub author: String,
     pub channel: String,
-    pub title: String,
     pub link: String,
     pub published: bool,
-    pub userid: i32,
 }
 
 #[derive(Insertable)]
@@ -27,7 +24,7 @@ pub struct NewVideos {
 
 #[derive(Queryable, Selectable)]
 #[diesel(table_name = crate::schema::youtube_users)]
-#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
 pub struct YoutubeUser {
     pub id: i32,
     pub username: String,
@@ -36,6 +33,9 @@ pub struct YoutubeUser {
     pub privatekey: String,
     pub channel: String,
     pub channel_id: String,
+    pub onboarded: bool,
+    pub bunker: Option<String>,
+    pub relay_list: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -48,3 +48,111 @@ pub struct NewYoutubeUser {
     pub channel: String,
     pub channel_id: String,
 }
+
+/// What the bot has seen of a relay, see [`crate::Store::record_relay_send`].
+#[derive(Debug, Clone, PartialEq, Eq, Queryable, Selectable, Insertable)]
+#[diesel(table_name = crate::schema::relay_health)]
+#[diesel(primary_key(url))]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
+pub struct RelayHealth {
+    pub url: String,
+    pub sent: i32,
+    pub accepted: i32,
+    pub rejected: i32,
+    /// Events the relay could not be reached for or did not answer in time.
+    pub failed: i32,
+    /// Events sent since the relay last accepted one.
+    pub consecutive_failures: i32,
+    /// Total latency of the accepted events, in milliseconds.
+    pub latency_ms: i64,
+    pub last_error: Option<String>,
+    /// Unix time the last event was sent.
+    pub last_attempt: i64,
+}
+
+impl RelayHealth {
+    /// A relay no event was sent to yet.
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            sent: 0,
+            accepted: 0,
+            rejected: 0,
+            failed: 0,
+            consecutive_failures: 0,
+            latency_ms: 0,
+            last_error: None,
+            last_attempt: 0,
+        }
+    }
+
+    /// Adds the outcome of an event sent at `at`.
+    pub fn record(&mut self, outcome: &RelayOutcome, at: i64) {
+        self.sent += 1;
+        self.last_attempt = at;
+        match outcome {
+            RelayOutcome::Accepted(latency) => {
+                self.accepted += 1;
+                self.consecutive_failures = 0;
+                self.latency_ms += latency.as_millis() as i64;
+            }
+            RelayOutcome::Rejected(message) => {
+                self.rejected += 1;
+                self.consecutive_failures += 1;
+                self.last_error = Some(message.clone());
+            }
+            RelayOutcome::Failed(message) => {
+                self.failed += 1;
+                self.consecutive_failures += 1;
+                self.last_error = Some(message.clone());
+            }
+        }
+    }
+
+    /// Whether the relay failed often enough in a row to be left out of
+    /// sends.
+    pub fn is_demoted(&self) -> bool {
+        self.consecutive_failures >= DEMOTE_AFTER
+    }
+
+    /// Whether a demoted relay is due for another attempt at unix time
+    /// `now`, so that a relay that comes back is promoted again.
+    pub fn retry_due(&self, now: i64) -> bool {
+        now - self.last_attempt >= DEMOTED_RETRY.as_secs() as i64
+    }
+
+    /// Share of the sent events the relay accepted.
+    pub fn acceptance_rate(&self) -> Option<f64> {
+        match self.sent {
+            0 => None,
+            sent => Some(self.accepted as f64 / sent as f64),
+        }
+    }
+
+    /// Mean time the relay took to accept an event.
+    pub fn average_latency(&self) -> Option<Duration> {
+        match self.accepted {
+            0 => None,
+            accepted => Some(Duration::from_millis(
+                (self.latency_ms / accepted as i64) as u64,
+            )),
+        }
+    }
+}
+
+/// Failures in a row after which a relay is demoted.
+pub const DEMOTE_AFTER: i32 = 5;
+
+/// How long a demoted relay is skipped before it is tried again.
+pub const DEMOTED_RETRY: Duration = Duration::from_secs(60 * 60);
+
+/// How a relay answered one event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelayOutcome {
+    /// Acknowledged after the given time.
+    Accepted(Duration),
+    /// Answered `OK false` with the message.
+    Rejected(String),
+    /// Could not be reached or did not answer in time.
+    Failed(String),
+}
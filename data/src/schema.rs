@@ -21,9 +21,26 @@ diesel::table! {
         privatekey -> Varchar,
         channel -> Varchar,
         channel_id -> Varchar,
+        onboarded -> Bool,
+        bunker -> Nullable<Varchar>,
+        relay_list -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    relay_health (url) {
+        url -> Varchar,
+        sent -> Int4,
+        accepted -> Int4,
+        rejected -> Int4,
+        failed -> Int4,
+        consecutive_failures -> Int4,
+        latency_ms -> Int8,
+        last_error -> Nullable<Varchar>,
+        last_attempt -> Int8,
     }
 }
 
 diesel::joinable!(videos -> youtube_users (userid));
 
-diesel::allow_tables_to_appear_in_same_query!(videos, youtube_users,);
+diesel::allow_tables_to_appear_in_same_query!(relay_health, videos, youtube_users,);
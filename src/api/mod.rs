@@ -2,5 +2,15 @@ mod rss;
 mod youtube;
 pub use rss::Error as RssError;
 pub use rss::RssFetcher;
+pub use rss::VideoInfo;
 pub use youtube::Error as YoutubeError;
 pub use youtube::YoutubeFetcher;
+
+/// Whether a failed request may succeed if retried: timeouts, connection
+/// failures, rate limiting and server errors.
+pub(crate) fn request_is_transient(e: &reqwest::Error) -> bool {
+    match e.status() {
+        Some(status) => status.is_server_error() || status.as_u16() == 429,
+        None => e.is_timeout() || e.is_connect() || e.is_request(),
+    }
+}
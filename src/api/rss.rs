@@ -1,3 +1,6 @@
+use crate::metrics::{metrics, outcome};
+use chrono::{DateTime, Utc};
+
 #[derive(Debug)]
 pub enum Error {
     Reqwest(reqwest::Error),
@@ -41,14 +44,48 @@ impl std::fmt::Display for Error {
     }
 }
 
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Reqwest(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::Serde(e) => Some(e),
+            Self::Rss(e) => Some(e),
+        }
+    }
+}
+
+impl Error {
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Reqwest(e) => super::request_is_transient(e),
+            Self::Io(_) => true,
+            Self::Serde(_) | Self::Rss(_) => false,
+        }
+    }
+}
+
 pub struct RssFetcher {
     url: String,
 }
 
+#[derive(Debug, Clone)]
 pub struct VideoInfo {
     pub title: String,
     pub link: String,
     pub author_name: String,
+    pub published: Option<DateTime<Utc>>,
+}
+
+impl VideoInfo {
+    /// The YouTube video id, taken from the `v` parameter of the link, or
+    /// the whole link if it has none.
+    pub fn id(&self) -> &str {
+        match self.link.split_once("v=") {
+            Some((_, rest)) => rest.split('&').next().unwrap_or(rest),
+            None => &self.link,
+        }
+    }
 }
 
 impl RssFetcher {
@@ -59,6 +96,15 @@ impl RssFetcher {
     }
 
     pub async fn fetch(&self) -> Result<Vec<VideoInfo>, Error> {
+        let res = self.fetch_videos().await;
+        metrics()
+            .feed_fetches
+            .with_label_values(&["rss", outcome(&res)])
+            .inc();
+        res
+    }
+
+    async fn fetch_videos(&self) -> Result<Vec<VideoInfo>, Error> {
         let content = reqwest::get(&self.url).await?.text().await?;
         let channel = content.parse::<rss::Channel>()?;
 
@@ -69,6 +115,10 @@ impl RssFetcher {
                 title: item.title().unwrap_or_default().to_string(),
                 link: item.link().unwrap_or_default().to_string(),
                 author_name: item.author().unwrap_or_default().to_string(),
+                published: item
+                    .pub_date()
+                    .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+                    .map(|date| date.with_timezone(&Utc)),
             })
             .collect();
 
@@ -1,3 +1,4 @@
+use crate::metrics::{metrics, outcome, CHANNELS_QUOTA, SEARCH_QUOTA};
 use sonic_rs::{JsonContainerTrait, JsonValueTrait, Value};
 
 pub struct YoutubeFetcher<'a> {
@@ -43,6 +44,27 @@ impl std::fmt::Display for Error {
     }
 }
 
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Reqwest(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::Sonic(e) => Some(e),
+            Self::Custom(_) => None,
+        }
+    }
+}
+
+impl Error {
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Reqwest(e) => super::request_is_transient(e),
+            Self::Io(_) => true,
+            Self::Sonic(_) | Self::Custom(_) => false,
+        }
+    }
+}
+
 pub struct UserInfo {
     pub avatar_link: String,
     pub user_name: String,
@@ -69,6 +91,7 @@ impl<'a> YoutubeFetcher<'a> {
             "https://www.googleapis.com/youtube/v3/search?part=snippet&q={}&type=channel&key={}",
             self.channel_name, self.api_key
         );
+        use_quota("search", SEARCH_QUOTA);
         let channel_response = reqwest::get(&channel_url).await?.text().await?;
         let channel_v: Value = sonic_rs::from_str(&channel_response)?;
         let channel_id = match channel_v["items"].get(0) {
@@ -89,6 +112,7 @@ impl<'a> YoutubeFetcher<'a> {
             "https://www.googleapis.com/youtube/v3/channels?part=snippet&id={}&key={}",
             channel_id, self.api_key
         );
+        use_quota("channels", CHANNELS_QUOTA);
         let user_info_response = reqwest::get(&user_info_url).await?.text().await?;
         let user_info_v: Value = sonic_rs::from_str(&user_info_response)?;
 
@@ -115,8 +139,18 @@ impl<'a> YoutubeFetcher<'a> {
     }
 
     pub async fn fetch(&self) -> Result<Vec<VideoInfo>, Error> {
+        let res = self.fetch_videos().await;
+        metrics()
+            .feed_fetches
+            .with_label_values(&["youtube", outcome(&res)])
+            .inc();
+        res
+    }
+
+    async fn fetch_videos(&self) -> Result<Vec<VideoInfo>, Error> {
         let channel_id = self.get_channel_id().await?;
         let video_url = format!("https://www.googleapis.com/youtube/v3/search?key={}&channelId={}&part=snippet,id&order=date&maxResults={}", self.api_key, channel_id, self.count);
+        use_quota("search", SEARCH_QUOTA);
         let video_response = reqwest::get(&video_url).await?.text().await?;
         let video_v: Value = sonic_rs::from_str(&video_response)?;
 
@@ -149,3 +183,10 @@ impl<'a> YoutubeFetcher<'a> {
         Ok(videos)
     }
 }
+
+fn use_quota(endpoint: &str, units: u64) {
+    metrics()
+        .youtube_quota
+        .with_label_values(&[endpoint])
+        .inc_by(units);
+}
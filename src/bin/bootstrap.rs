@@ -1,67 +1,286 @@
-use core::time;
-use log::{error, info};
-use std::collections::HashMap;
-use tokio::time::sleep;
-use youtube_bot::load_conf;
+use clap::Parser;
+use nostr_sdk::ToBech32;
+use std::path::Path;
+use std::process::ExitCode;
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info};
+use youtube_bot::parse_secret_key;
 use youtube_bot::App;
-
-const CONF_PATH: &str = "./conf/test/config.yaml";
+use youtube_bot::Bot;
+use youtube_bot::ChannelCommand;
+use youtube_bot::Cli;
+use youtube_bot::Command;
+use youtube_bot::Config;
+use youtube_bot::ConfigCommand;
+use youtube_bot::DbConnection;
+use youtube_bot::HttpServer;
+use youtube_bot::KeyBackup;
 
 #[tokio::main]
-async fn main() {
-    env_logger::init();
+async fn main() -> ExitCode {
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+    cli.config.init_logger();
+
+    if let Some(Command::Config(ConfigCommand::Check)) = cli.command {
+        return check_config(&cli);
+    }
 
-    let conf = match load_conf(CONF_PATH) {
+    let conf = match cli.config.load_conf() {
         Ok(conf) => conf,
         Err(e) => {
             error!("Failed to load config: {}", e);
-            return;
+            return ExitCode::FAILURE;
         }
     };
 
-    let relays = conf.nostr.relays;
-    let users = conf.youtube.user_id;
-    let api_key = conf.youtube.api_key;
-    let count = conf.youtube.count;
-    let dsn = conf.postgres.dsn;
+    if let Some(Command::Channel(command)) = &cli.command {
+        return channel_command(&cli, &conf, command).await;
+    }
+    if let Some(Command::Relays) = cli.command {
+        return relay_health(&conf);
+    }
 
-    let mut apps = HashMap::new();
-    let mut user_contents = HashMap::new();
+    let http = conf.http.clone();
+    let updates = cli.config.watch_conf(conf);
+    let bot = Bot::new(updates.clone(), cli.dry_run);
+    if let Some(http) = http {
+        match HttpServer::bind(http.listen, bot.status(), updates).await {
+            Ok(server) => {
+                tokio::spawn(server.dry_run(cli.dry_run).run());
+            }
+            Err(e) => {
+                error!("Failed to start HTTP server on {}: {}", http.listen, e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    match bot.run(cli.once, shutdown_signal()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("Unclean shutdown: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    // Initialize app instances and fetch initial contents
-    for user in &users {
-        match App::new(&dsn) {
-            Ok(mut app) => match app.check_user(user, &api_key, count).await {
-                Ok(channel_id) => match app.get_contents(&channel_id, user).await {
-                    Ok(contents) => {
-                        apps.insert(user.clone(), app);
-                        user_contents.insert(user.clone(), contents);
-                    }
-                    Err(e) => error!("Failed to get contents for user {}: {}", user, e),
-                },
-                Err(e) => error!("Failed to check user {}: {}", user, e),
-            },
-            Err(e) => error!("Failed to create app for user {}: {}", user, e),
+fn check_config(cli: &Cli) -> ExitCode {
+    match cli.config.load_conf() {
+        Ok(_) => {
+            println!("config OK");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            println!("{}", e);
+            ExitCode::FAILURE
         }
     }
+}
 
-    // Main loop to publish one post per user per iteration
-    let mut done = false;
-    while !done {
-        done = true;
-        for user in &users {
-            if let Some(contents) = user_contents.get_mut(user) {
-                if let Some(msg) = contents.pop() {
-                    done = false;
-                    if let Some(app) = apps.get_mut(user) {
-                        match app.publish(user, &msg, &relays).await {
-                            Ok(_) => info!("Successfully published for user {}", user),
-                            Err(e) => error!("Failed to publish for user {}: {}", user, e),
-                        }
-                        sleep(time::Duration::from_secs(600)).await;
-                    }
-                }
+async fn channel_command(cli: &Cli, conf: &Config, command: &ChannelCommand) -> ExitCode {
+    let name = match command {
+        ChannelCommand::Export { name, passphrase } => {
+            return export_keys(conf, name.as_deref(), passphrase).await
+        }
+        ChannelCommand::Import { file, passphrase } => {
+            return import_keys(cli, conf, file, passphrase).await
+        }
+        ChannelCommand::Add { name, .. }
+        | ChannelCommand::SetKey { name, .. }
+        | ChannelCommand::Connect { name, .. } => name,
+    };
+    let settings = match conf.channel_settings(name) {
+        Some(settings) => settings,
+        None => {
+            error!("Channel {} is not in the config", name);
+            return ExitCode::FAILURE;
+        }
+    };
+    let key = match command {
+        ChannelCommand::Add { key, .. } => key.as_deref(),
+        ChannelCommand::SetKey { key, .. } => Some(key.as_str()),
+        _ => None,
+    };
+    let keys = match key.map(parse_secret_key).transpose() {
+        Ok(keys) => keys,
+        Err(e) => {
+            error!("Invalid key: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut app = match App::new(&conf.postgres.dsn) {
+        Ok(app) => app
+            .dry_run(cli.dry_run)
+            .mnemonic(conf.nostr.mnemonic.clone())
+            .nip05_domain(conf.nostr.nip05_domain.clone()),
+        Err(e) => {
+            error!("Failed to create app: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match (command, keys) {
+        (ChannelCommand::SetKey { .. }, Some(keys)) => app
+            .replace_key(name, &keys, &settings.relays)
+            .await
+            .map(|()| match cli.dry_run {
+                true => format!("Would replace the key of channel {}", name),
+                false => format!("Replaced the key of channel {}", name),
+            }),
+        (ChannelCommand::Connect { bunker, .. }, _) => app
+            .connect_bunker(name, bunker, &settings.relays)
+            .await
+            .map(|public_key| {
+                let npub = public_key
+                    .to_bech32()
+                    .unwrap_or_else(|_| public_key.to_hex());
+                format!("Channel {} now signs as {}", name, npub)
+            }),
+        (_, keys) => app
+            .add_channel(name, &conf.youtube.api_key, conf.youtube.count, keys)
+            .await
+            .map(|channel_id| match cli.dry_run {
+                true => format!("Would add channel {} ({})", name, channel_id),
+                false => format!("Added channel {} ({})", name, channel_id),
+            }),
+    };
+    match result {
+        Ok(done) => {
+            println!("{}", done);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn export_keys(conf: &Config, name: Option<&str>, passphrase: &str) -> ExitCode {
+    let mut app = match App::new(&conf.postgres.dsn) {
+        Ok(app) => app,
+        Err(e) => {
+            error!("Failed to create app: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    match app.export_keys(name, passphrase).await {
+        Ok(backups) => {
+            for backup in backups {
+                println!("{}", backup);
             }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::FAILURE
         }
     }
 }
+
+/// Imports every key of `file`, reporting each channel that fails.
+async fn import_keys(cli: &Cli, conf: &Config, file: &Path, passphrase: &str) -> ExitCode {
+    let contents = match std::fs::read_to_string(file) {
+        Ok(contents) => contents,
+        Err(e) => {
+            error!("Failed to read {}: {}", file.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut app = match App::new(&conf.postgres.dsn) {
+        Ok(app) => app.dry_run(cli.dry_run),
+        Err(e) => {
+            error!("Failed to create app: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut status = ExitCode::SUCCESS;
+    let lines = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    for line in lines {
+        let backup = match line.parse::<KeyBackup>() {
+            Ok(backup) => backup,
+            Err(e) => {
+                error!("{}", e);
+                status = ExitCode::FAILURE;
+                continue;
+            }
+        };
+        let imported = app
+            .import_key(
+                &backup,
+                passphrase,
+                &conf.youtube.api_key,
+                conf.youtube.count,
+            )
+            .await;
+        match imported {
+            Ok(true) => println!("Imported channel {}", backup.channel),
+            Ok(false) => println!("Channel {} already has this key", backup.channel),
+            Err(e) => {
+                error!("Failed to import channel {}: {}", backup.channel, e);
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    status
+}
+
+/// Prints the health of every relay events were sent to.
+fn relay_health(conf: &Config) -> ExitCode {
+    let relays = DbConnection::new(&conf.postgres.dsn).and_then(|mut db| db.load_relay_health());
+    let relays = match relays {
+        Ok(relays) => relays,
+        Err(e) => {
+            error!("Failed to load relay health: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!(
+        "{:<40} {:<8} {:>6} {:>8} {:>9} {:>8}  LAST ERROR",
+        "RELAY", "STATUS", "SENT", "ACCEPTED", "LATENCY", "FAILURES"
+    );
+    for relay in relays {
+        let status = match relay.is_demoted() {
+            true => "demoted",
+            false => "ok",
+        };
+        let accepted = relay
+            .acceptance_rate()
+            .map(|rate| format!("{:.0}%", rate * 100.0))
+            .unwrap_or_else(|| "-".to_string());
+        let latency = relay
+            .average_latency()
+            .map(|latency| format!("{}ms", latency.as_millis()))
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{:<40} {:<8} {:>6} {:>8} {:>9} {:>8}  {}",
+            relay.url,
+            status,
+            relay.sent,
+            accepted,
+            latency,
+            relay.consecutive_failures,
+            relay.last_error.as_deref().unwrap_or("-")
+        );
+    }
+    ExitCode::SUCCESS
+}
+
+/// Completes on the first SIGINT or SIGTERM.
+async fn shutdown_signal() {
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(sigterm) => sigterm,
+        Err(e) => {
+            error!("Failed to listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => info!("Received SIGINT"),
+        _ = sigterm.recv() => info!("Received SIGTERM"),
+    }
+}
@@ -1,24 +1,38 @@
+use clap::Parser;
 use data::DbConnection;
-use youtube_bot::load_conf;
-
-const CONF_PATH: &str = "./conf/test/config.yaml";
+use std::process::ExitCode;
+use youtube_bot::MigrationCli;
 
 #[tokio::main]
-async fn main() {
-    env_logger::init();
-    let conf = load_conf(CONF_PATH).expect("Failed to load config");
+async fn main() -> ExitCode {
+    dotenv::dotenv().ok();
+    let cli = MigrationCli::parse();
+    cli.config.init_logger();
+    let conf = match cli.config.load_conf() {
+        Ok(conf) => conf,
+        Err(e) => {
+            tracing::error!("Failed to load config: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
 
     let dsn = conf.postgres.dsn;
     let mut db_conn = match DbConnection::new(&dsn) {
         Ok(conn) => conn,
         Err(e) => {
-            log::error!("Failed to create database connection: {}", e);
-            return;
+            tracing::error!("Failed to create database connection: {}", e);
+            return ExitCode::FAILURE;
         }
     };
 
     match db_conn.run_migrations() {
-        Ok(_) => log::info!("Migrations ran successfully"),
-        Err(e) => log::error!("Failed to run migrations: {}", e),
+        Ok(_) => {
+            tracing::info!("Migrations ran successfully");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            tracing::error!("Failed to run migrations: {}", e);
+            ExitCode::FAILURE
+        }
     }
 }
@@ -0,0 +1,170 @@
+use crate::conf::Config;
+use crate::limit::PostLimiter;
+use crate::Error;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::{watch, Semaphore};
+use tokio::task::{AbortHandle, JoinSet};
+
+/// How long in-flight fetches and publishes may take to finish on shutdown.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
+
+mod status;
+mod worker;
+pub use status::StatusBoard;
+use worker::Worker;
+
+/// State shared by the channel workers.
+pub(crate) struct Shared {
+    pub limiter: Mutex<PostLimiter>,
+    /// Bounds how many channels fetch or publish at the same time.
+    pub permits: Semaphore,
+    pub status: Arc<StatusBoard>,
+    pub dry_run: bool,
+}
+
+/// Runs one worker task per configured channel, so that a slow channel or
+/// one waiting on a publish limit never holds up the others.
+pub struct Bot {
+    updates: watch::Receiver<Arc<Config>>,
+    shared: Arc<Shared>,
+    shutdown: watch::Sender<bool>,
+    workers: JoinSet<()>,
+    running: HashMap<String, AbortHandle>,
+}
+
+impl Bot {
+    pub fn new(mut updates: watch::Receiver<Arc<Config>>, dry_run: bool) -> Self {
+        let conf = updates.borrow_and_update().clone();
+        let shared = Shared {
+            limiter: Mutex::new(PostLimiter::new(conf.publish.clone())),
+            permits: Semaphore::new(conf.youtube.concurrency),
+            status: Arc::new(StatusBoard::default()),
+            dry_run,
+        };
+        Self {
+            updates,
+            shared: Arc::new(shared),
+            shutdown: watch::Sender::new(false),
+            workers: JoinSet::new(),
+            running: HashMap::new(),
+        }
+    }
+
+    /// Per-channel status, kept up to date while the bot runs.
+    pub fn status(&self) -> Arc<StatusBoard> {
+        self.shared.status.clone()
+    }
+
+    /// Polls and publishes until `shutdown` completes. With `once`, every
+    /// channel is fetched a single time and the bot returns when all queues
+    /// are drained.
+    ///
+    /// On shutdown no new fetch or publish is started, the ones in flight are
+    /// given [`SHUTDOWN_GRACE`] to finish and queued videos are handed back to
+    /// the next poll. Fails if in-flight work had to be aborted.
+    pub async fn run(
+        mut self,
+        once: bool,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<(), Error> {
+        let mut conf = self.updates.borrow().clone();
+        self.spawn_missing(&conf, once);
+        tokio::pin!(shutdown);
+
+        loop {
+            if once && self.workers.is_empty() {
+                return Ok(());
+            }
+            tokio::select! {
+                _ = &mut shutdown => return self.drain().await,
+                Some(res) = self.workers.join_next() => {
+                    if let Err(e) = res {
+                        tracing::error!(error = %e, "Channel worker failed");
+                    }
+                    // the config may have changed since `conf` was applied
+                    if !once {
+                        let latest = self.updates.borrow().clone();
+                        self.spawn_missing(&latest, once);
+                    }
+                }
+                changed = self.updates.changed(), if !once => {
+                    if changed.is_err() {
+                        continue;
+                    }
+                    let new = self.updates.borrow_and_update().clone();
+                    self.reload(&conf, &new);
+                    conf = new;
+                }
+            }
+        }
+    }
+
+    async fn drain(mut self) -> Result<(), Error> {
+        tracing::info!(workers = self.workers.len(), "Shutting down");
+        self.shutdown.send_replace(true);
+        let drained = tokio::time::timeout(SHUTDOWN_GRACE, async {
+            while let Some(res) = self.workers.join_next().await {
+                if let Err(e) = res {
+                    tracing::error!(error = %e, "Channel worker failed");
+                }
+            }
+        })
+        .await;
+        match drained {
+            Ok(()) => {
+                tracing::info!("Shutdown complete");
+                Ok(())
+            }
+            Err(_) => {
+                let aborted = self.workers.len();
+                self.workers.shutdown().await;
+                Err(Error::Custom(format!(
+                    "aborted {} channel workers still busy after {}s",
+                    aborted,
+                    SHUTDOWN_GRACE.as_secs()
+                )))
+            }
+        }
+    }
+
+    /// Applies the parts of a new config that are shared by every worker and
+    /// starts workers for new channels; workers of removed channels stop on
+    /// their own.
+    fn reload(&mut self, old: &Config, new: &Config) {
+        if old.publish != new.publish {
+            self.shared
+                .limiter
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .set_config(new.publish.clone());
+        }
+        if old.youtube.concurrency != new.youtube.concurrency {
+            tracing::warn!("youtube.concurrency changes take effect after a restart");
+        }
+        if old.http != new.http {
+            tracing::warn!("http changes take effect after a restart");
+        }
+        self.spawn_missing(new, false);
+    }
+
+    fn spawn_missing(&mut self, conf: &Config, once: bool) {
+        self.running.retain(|_, handle| !handle.is_finished());
+        for name in conf.channel_names() {
+            if self.running.contains_key(name) {
+                continue;
+            }
+            tracing::info!(channel = %name, "Started polling");
+            let worker = Worker::new(
+                name,
+                self.shared.clone(),
+                self.updates.clone(),
+                self.shutdown.subscribe(),
+            );
+            let handle = self.workers.spawn(worker.run(once));
+            self.running.insert(name.to_string(), handle);
+        }
+    }
+}
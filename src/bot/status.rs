@@ -0,0 +1,58 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+
+/// What the bot last did for one channel.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChannelStatus {
+    pub last_poll: Option<DateTime<Utc>>,
+    pub last_publish: Option<DateTime<Utc>>,
+    pub queue_depth: usize,
+    pub last_error: Option<ErrorStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorStatus {
+    pub time: DateTime<Utc>,
+    pub message: String,
+}
+
+/// Status of every running channel, updated by the channel workers.
+#[derive(Debug, Default)]
+pub struct StatusBoard {
+    channels: Mutex<BTreeMap<String, ChannelStatus>>,
+}
+
+impl StatusBoard {
+    pub fn update<F>(&self, channel: &str, f: F)
+    where
+        F: FnOnce(&mut ChannelStatus),
+    {
+        let mut channels = self.channels.lock().unwrap_or_else(PoisonError::into_inner);
+        f(channels.entry(channel.to_string()).or_default());
+    }
+
+    pub fn error(&self, channel: &str, message: String) {
+        self.update(channel, |status| {
+            status.last_error = Some(ErrorStatus {
+                time: Utc::now(),
+                message,
+            })
+        });
+    }
+
+    pub fn remove(&self, channel: &str) {
+        self.channels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(channel);
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, ChannelStatus> {
+        self.channels
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
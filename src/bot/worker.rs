@@ -0,0 +1,608 @@
+use super::Shared;
+use crate::conf::{ChannelSettings, Config};
+use crate::metrics::metrics;
+use crate::nostr::{render_template, Note};
+use crate::{App, ChatGPTClient, Error, VideoInfo};
+use chrono::Utc;
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, PoisonError};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::{sleep_until, Instant};
+
+/// Delay before polling again after a transient failure.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Failed attempts to publish a video before the bot gives up on it.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// What became of an attempt to publish a video.
+#[derive(Debug, PartialEq, Eq)]
+enum Attempt {
+    Published,
+    /// The failure may clear up; the video is published again after
+    /// [`RETRY_DELAY`].
+    Retry,
+    Failed,
+}
+
+/// Polls one channel and publishes its videos. Each worker owns its database
+/// connection and queue, so channels only share the publish limits and the
+/// concurrency permits.
+pub(crate) struct Worker {
+    name: String,
+    shared: Arc<Shared>,
+    updates: watch::Receiver<Arc<Config>>,
+    shutdown: watch::Receiver<bool>,
+    conf: Arc<Config>,
+    app: Option<App>,
+    queue: VecDeque<VideoInfo>,
+    /// Failed attempts to publish each video, by link.
+    attempts: HashMap<String, u32>,
+    next_poll: Instant,
+    gpt: Option<ChatGPTClient>,
+    once: bool,
+}
+
+impl Worker {
+    pub fn new(
+        name: &str,
+        shared: Arc<Shared>,
+        mut updates: watch::Receiver<Arc<Config>>,
+        shutdown: watch::Receiver<bool>,
+    ) -> Self {
+        let conf = updates.borrow_and_update().clone();
+        Self {
+            name: name.to_string(),
+            shared,
+            updates,
+            shutdown,
+            gpt: gpt_client(&conf),
+            conf,
+            app: None,
+            queue: VecDeque::new(),
+            attempts: HashMap::new(),
+            next_poll: Instant::now(),
+            once: false,
+        }
+    }
+
+    /// Runs until the channel is removed from the config or the bot shuts
+    /// down. With `once`, the channel is fetched a single time and the worker
+    /// returns when its queue is drained.
+    pub async fn run(mut self, once: bool) {
+        self.once = once;
+        if self.conf.channel_settings(&self.name).is_none() {
+            // removed by a reload before the worker started
+            return;
+        }
+        if once {
+            self.fetch().await;
+            while let Some(wait) = self.publish_ready().await {
+                if !self.wait(wait).await {
+                    break;
+                }
+            }
+        } else {
+            loop {
+                if self.next_poll <= Instant::now() {
+                    self.fetch().await;
+                }
+                let poll_wait = self.next_poll.saturating_duration_since(Instant::now());
+                let wait = match self.publish_ready().await {
+                    Some(publish_wait) => poll_wait.min(publish_wait),
+                    None => poll_wait,
+                };
+                if !self.wait(wait).await {
+                    break;
+                }
+            }
+        }
+        self.give_back().await;
+    }
+
+    fn queue_changed(&self) {
+        let depth = self.queue.len();
+        self.shared
+            .status
+            .update(&self.name, |status| status.queue_depth = depth);
+        metrics()
+            .queue_depth
+            .with_label_values(&[&self.name])
+            .set(depth as i64);
+    }
+
+    fn stopping(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// Hands the queued videos back to the next poll, so a shutdown or the
+    /// channel's removal does not lose videos that were seen but not
+    /// published.
+    async fn give_back(&mut self) {
+        if self.queue.is_empty() {
+            return;
+        }
+        let videos: Vec<VideoInfo> = self.queue.drain(..).collect();
+        self.queue_changed();
+        // a reload may have dropped the connection since the last poll
+        let app = match self.app() {
+            Some(app) => app,
+            None => return,
+        };
+        match app.forget_videos(&videos).await {
+            Ok(()) => tracing::info!(
+                channel = %self.name,
+                videos = videos.len(),
+                "Returned queued videos to the feed"
+            ),
+            Err(e) => tracing::error!(
+                channel = %self.name,
+                error = %e,
+                "Failed to return queued videos"
+            ),
+        }
+    }
+
+    /// Returns the app, connecting to the database on first use.
+    fn app(&mut self) -> Option<&mut App> {
+        if self.app.is_none() {
+            match App::new(&self.conf.postgres.dsn) {
+                Ok(app) => {
+                    let app = app
+                        .dry_run(self.shared.dry_run)
+                        .mnemonic(self.conf.nostr.mnemonic.clone())
+                        .nip05_domain(self.conf.nostr.nip05_domain.clone());
+                    self.app = Some(app);
+                }
+                Err(e) => {
+                    tracing::error!(channel = %self.name, error = %e, "Failed to create app");
+                    self.shared.status.error(&self.name, e.to_string());
+                    return None;
+                }
+            }
+        }
+        self.app.as_mut()
+    }
+
+    #[tracing::instrument(name = "poll", skip_all, fields(channel = %self.name))]
+    async fn fetch(&mut self) {
+        let conf = self.conf.clone();
+        let user = self.name.clone();
+        let settings = match conf.channel_settings(&user) {
+            Some(settings) => settings,
+            None => return,
+        };
+        self.next_poll = Instant::now() + settings.interval;
+        let shared = self.shared.clone();
+        let _permit = shared.permits.acquire().await;
+        if self.stopping() {
+            return;
+        }
+        let status = &shared.status;
+        status.update(&user, |status| status.last_poll = Some(Utc::now()));
+        let app = match self.app() {
+            Some(app) => app,
+            None => return,
+        };
+        if let Err(e) = app.update_relay_list(&user, &settings.relays).await {
+            tracing::warn!(error = %e, "Failed to publish relay list");
+            status.error(&user, format!("Failed to publish relay list: {}", e));
+        }
+
+        match app
+            .check_user(&user, &conf.youtube.api_key, conf.youtube.count)
+            .await
+        {
+            Ok(channel_id) => match app
+                .get_contents(&channel_id, &user, settings.onboarding)
+                .await
+            {
+                Ok(mut contents) => {
+                    metrics()
+                        .videos_discovered
+                        .with_label_values(&[&user])
+                        .inc_by(contents.len() as u64);
+                    tracing::debug!(videos = contents.len(), "Fetched new videos");
+                    if let Some(limit) = settings.backfill_limit {
+                        if contents.len() > limit {
+                            tracing::info!(
+                                skipped = contents.len() - limit,
+                                "Skipping videos over the backfill limit"
+                            );
+                            contents.sort_by_key(|video| Reverse(video.published));
+                            contents.truncate(limit);
+                        }
+                    }
+                    // oldest first, videos without a publish time last
+                    self.queue.extend(contents);
+                    self.queue
+                        .make_contiguous()
+                        .sort_by_key(|video| (video.published.is_none(), video.published));
+                    self.queue_changed();
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "Failed to get contents");
+                    self.fetch_failed(&e);
+                }
+            },
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to check user");
+                self.fetch_failed(&e);
+            }
+        }
+    }
+
+    /// Records a failed poll and, if the failure may clear up, polls again
+    /// after [`RETRY_DELAY`] rather than a full interval.
+    fn fetch_failed(&mut self, e: &Error) {
+        self.shared.status.error(&self.name, e.to_string());
+        self.check_connection(e);
+        let retry = Instant::now() + RETRY_DELAY;
+        if e.is_transient() && retry < self.next_poll {
+            tracing::info!(retry_in = RETRY_DELAY.as_secs(), "Retrying poll early");
+            self.next_poll = retry;
+        }
+    }
+
+    /// Drops the database connection after an error that may have lost it,
+    /// such as a restart of Postgres. Diesel does not reconnect, so the next
+    /// use of [`Worker::app`] connects again.
+    fn check_connection(&mut self, e: &Error) {
+        if matches!(e, Error::DbError(_)) && e.is_transient() {
+            tracing::warn!(channel = %self.name, "Reconnecting to the database");
+            self.app = None;
+        }
+    }
+
+    /// Publishes queued videos, oldest first, as far as the publish limits
+    /// allow. Returns how long until the next queued video may be published,
+    /// or `None` if the queue is empty.
+    async fn publish_ready(&mut self) -> Option<Duration> {
+        let conf = self.conf.clone();
+        let settings = conf.channel_settings(&self.name)?;
+        while !self.queue.is_empty() && !self.stopping() {
+            // keep the queue for the next round if the database is unreachable
+            self.app()?;
+            if !self.shared.dry_run {
+                let acquired = self
+                    .shared
+                    .limiter
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .acquire(&self.name, settings.posting_window);
+                if let Err(wait) = acquired {
+                    return Some(wait);
+                }
+            }
+            let video = self.queue.pop_front()?;
+            let shared = self.shared.clone();
+            let _permit = shared.permits.acquire().await;
+            if self.stopping() {
+                self.queue.push_front(video);
+                return None;
+            }
+            let attempt = self.publish(&video, &settings).await;
+            if attempt != Attempt::Published && !self.shared.dry_run {
+                // failures do not count against the publish limits
+                self.shared
+                    .limiter
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .release(&self.name);
+            }
+            if attempt == Attempt::Retry {
+                self.queue.push_front(video);
+                // a single run hands the video back to the next one instead
+                return match self.once {
+                    true => None,
+                    false => Some(RETRY_DELAY),
+                };
+            }
+            self.queue_changed();
+        }
+        None
+    }
+
+    /// Publishes the note announcing `video`. A video failing for good is
+    /// forgotten, so that the next poll finds it again. After
+    /// [`MAX_ATTEMPTS`] failed attempts the video is dropped but stays
+    /// seen, so that it is not published at all.
+    #[tracing::instrument(
+        name = "publish",
+        skip_all,
+        fields(channel = %self.name, video = %video.id(), event_id = tracing::field::Empty)
+    )]
+    async fn publish(&mut self, video: &VideoInfo, settings: &ChannelSettings) -> Attempt {
+        let note = compose_note(video, settings, self.gpt.as_ref()).await;
+        let app = match self.app.as_mut() {
+            Some(app) => app,
+            None => return Attempt::Retry,
+        };
+        let start = Instant::now();
+        let published = app.publish(&self.name, &note, &settings.relays).await;
+        let status = &self.shared.status;
+        match published {
+            Ok(published) => {
+                tracing::Span::current().record("event_id", published.event_id.to_hex());
+                if let Some(e) = published.metadata_error {
+                    tracing::warn!(error = %e, "Failed to set metadata");
+                    status.error(&self.name, format!("Failed to set metadata: {}", e));
+                }
+                if let Some(e) = published.relay_list_error {
+                    tracing::warn!(error = %e, "Failed to publish relay list");
+                    status.error(&self.name, format!("Failed to publish relay list: {}", e));
+                }
+                if !self.shared.dry_run {
+                    metrics()
+                        .publish_duration
+                        .observe(start.elapsed().as_secs_f64());
+                    tracing::info!("Published note");
+                    status.update(&self.name, |status| status.last_publish = Some(Utc::now()));
+                }
+                self.attempts.remove(&video.link);
+                Attempt::Published
+            }
+            Err(e) => self.publish_failed(video, e).await,
+        }
+    }
+
+    /// Records a failed attempt to publish `video` and decides what becomes
+    /// of it.
+    async fn publish_failed(&mut self, video: &VideoInfo, e: Error) -> Attempt {
+        self.shared.status.error(&self.name, e.to_string());
+        self.check_connection(&e);
+        let attempts = self.attempts.entry(video.link.clone()).or_insert(0);
+        *attempts += 1;
+        if *attempts >= MAX_ATTEMPTS {
+            tracing::error!(
+                error = %e,
+                attempts = MAX_ATTEMPTS,
+                "Failed to publish, giving up on the video"
+            );
+            self.attempts.remove(&video.link);
+            return Attempt::Failed;
+        }
+        if e.is_transient() {
+            tracing::warn!(
+                error = %e,
+                retry_in = RETRY_DELAY.as_secs(),
+                "Failed to publish, retrying"
+            );
+            return Attempt::Retry;
+        }
+        tracing::error!(error = %e, "Failed to publish");
+        if let Some(app) = self.app() {
+            if let Err(e) = app.forget_videos(std::slice::from_ref(video)).await {
+                tracing::error!(error = %e, "Failed to return video to the feed");
+            }
+        }
+        Attempt::Failed
+    }
+
+    /// Sleeps for `duration`, applying config reloads as they arrive. Returns
+    /// false if the channel was removed from the config or the bot is
+    /// shutting down.
+    async fn wait(&mut self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        loop {
+            if self.stopping() {
+                return false;
+            }
+            tokio::select! {
+                _ = sleep_until(deadline) => return true,
+                _ = self.shutdown.changed() => return false,
+                changed = self.updates.changed() => {
+                    if changed.is_err() {
+                        sleep_until(deadline).await;
+                        return true;
+                    }
+                    if !self.reload() {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Switches to the latest config. Publishing only happens between waits,
+    /// so no note is interrupted by a reload.
+    fn reload(&mut self) -> bool {
+        let conf = self.updates.borrow_and_update().clone();
+        let old = match self.conf.channel_settings(&self.name) {
+            Some(settings) => settings,
+            None => return false,
+        };
+        let new = match conf.channel_settings(&self.name) {
+            Some(settings) => settings,
+            None => {
+                tracing::info!(channel = %self.name, "Stopped polling");
+                self.shared.status.remove(&self.name);
+                let _ = metrics().queue_depth.remove_label_values(&[&self.name]);
+                self.shared
+                    .limiter
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .remove(&self.name);
+                return false;
+            }
+        };
+        if self.conf.gpt != conf.gpt {
+            self.gpt = gpt_client(&conf);
+        }
+        if self.conf.postgres.dsn != conf.postgres.dsn {
+            tracing::warn!(channel = %self.name, "postgres.dsn changed, reconnecting");
+            self.app = None;
+        } else if self.conf.nostr.mnemonic != conf.nostr.mnemonic
+            || self.conf.nostr.nip05_domain != conf.nostr.nip05_domain
+        {
+            tracing::info!(channel = %self.name, "Identity settings changed");
+            self.app = None;
+        }
+        if old.relays != new.relays {
+            tracing::info!(
+                channel = %self.name,
+                relays = new.relays.len(),
+                "Relay set changed"
+            );
+        }
+        self.conf = conf;
+        true
+    }
+}
+
+/// Renders the note announcing `video` with the channel's settings. When
+/// GPT is enabled for the channel but the rewrite fails, the plain template
+/// is used.
+async fn compose_note(
+    video: &VideoInfo,
+    settings: &ChannelSettings,
+    gpt: Option<&ChatGPTClient>,
+) -> Note {
+    let mut content = render_template(&settings.template, video);
+    if let (true, Some(gpt)) = (settings.gpt, gpt) {
+        match gpt
+            .rewrite_note(&content, settings.language.as_deref())
+            .await
+        {
+            Ok(rewritten) => content = rewritten,
+            Err(e) => tracing::warn!(error = %e, "Failed to rewrite note with GPT"),
+        }
+    }
+    Note::new(content, video, settings)
+}
+
+fn gpt_client(conf: &Config) -> Option<ChatGPTClient> {
+    let gpt = conf.gpt.as_ref()?;
+    match ChatGPTClient::new(&gpt.api_key) {
+        Ok(client) => Some(client),
+        Err(e) => {
+            tracing::error!("Failed to create GPT client: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::StatusBoard;
+    use crate::limit::PostLimiter;
+    use crate::nostr::NotePublisher;
+    use data::{DbConnection, Store};
+    use nostr_sdk::{Keys, ToBech32};
+    use std::sync::Mutex;
+    use tokio::sync::Semaphore;
+
+    const CHANNEL: &str = "channel";
+
+    /// Refuses connections, so every send fails.
+    const UNREACHABLE: &str = "ws://127.0.0.1:1";
+
+    fn video() -> VideoInfo {
+        VideoInfo {
+            title: "Video".to_string(),
+            link: "https://www.youtube.com/watch?v=video".to_string(),
+            author_name: "Author".to_string(),
+            published: None,
+        }
+    }
+
+    /// A worker with `video()` seen and queued, publishing to `relay`.
+    async fn worker(relay: &str) -> Worker {
+        let mut db = DbConnection::new("sqlite://:memory:").unwrap();
+        db.run_migrations().unwrap();
+        let keys = Keys::generate();
+        db.add_user(
+            "Author",
+            "https://example.com/avatar.jpg",
+            &keys.public_key().to_bech32().unwrap(),
+            &keys.secret_key().unwrap().to_bech32().unwrap(),
+            CHANNEL,
+            "UC0123456789",
+        )
+        .await
+        .unwrap();
+        let video = video();
+        db.add_video("Author", CHANNEL, &video.title, &video.link, false)
+            .await
+            .unwrap();
+
+        let conf: Config = serde_yaml::from_str(&format!(
+            "
+youtube: {{api_key: key, user_id: [{}], count: 5}}
+nostr: {{relays: ['{}']}}
+postgres: {{dsn: 'sqlite://unused.db'}}
+",
+            CHANNEL, relay
+        ))
+        .unwrap();
+        let shared = Shared {
+            limiter: Mutex::new(PostLimiter::new(conf.publish.clone())),
+            permits: Semaphore::new(1),
+            status: Arc::new(StatusBoard::default()),
+            dry_run: false,
+        };
+        let (_, updates) = watch::channel(Arc::new(conf));
+        let (_, shutdown) = watch::channel(false);
+        let mut worker = Worker::new(CHANNEL, Arc::new(shared), updates, shutdown);
+        let publisher = NotePublisher::new().timeout(Duration::from_millis(500));
+        worker.app = Some(App::with_store(db).with_publisher(publisher));
+        worker.queue.push_back(video);
+        worker
+    }
+
+    async fn video_seen(worker: Worker) -> bool {
+        let mut db = worker.app.unwrap().into_store();
+        db.video_exists(&video().link).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn failed_note_is_retried() {
+        let mut worker = worker(UNREACHABLE).await;
+
+        assert_eq!(worker.publish_ready().await, Some(RETRY_DELAY));
+        assert_eq!(
+            worker.queue.front().map(|v| v.link.clone()),
+            Some(video().link)
+        );
+        // the failed attempt did not use up the identity's only token
+        assert_eq!(worker.publish_ready().await, Some(RETRY_DELAY));
+        assert!(video_seen(worker).await);
+    }
+
+    #[tokio::test]
+    async fn video_is_dropped_after_the_last_attempt() {
+        let mut worker = worker(UNREACHABLE).await;
+
+        for _ in 1..MAX_ATTEMPTS {
+            assert_eq!(worker.publish_ready().await, Some(RETRY_DELAY));
+        }
+        assert_eq!(worker.publish_ready().await, None);
+        assert!(worker.queue.is_empty());
+        assert!(worker.attempts.is_empty());
+        // not found again by the next poll
+        assert!(video_seen(worker).await);
+    }
+
+    #[tokio::test]
+    async fn failed_note_is_given_back_after_a_single_run() {
+        let mut worker = worker(UNREACHABLE).await;
+        worker.once = true;
+
+        assert_eq!(worker.publish_ready().await, None);
+        assert_eq!(worker.queue.len(), 1);
+        worker.give_back().await;
+        assert!(!video_seen(worker).await);
+    }
+
+    #[tokio::test]
+    async fn note_failing_for_good_is_returned_to_the_feed() {
+        let mut worker = worker("not a relay").await;
+
+        assert_eq!(worker.publish_ready().await, None);
+        assert!(worker.queue.is_empty());
+        assert!(!video_seen(worker).await);
+    }
+}
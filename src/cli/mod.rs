@@ -0,0 +1,169 @@
+use crate::conf::{self, Config};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+
+/// Config file used when `--config` is not given.
+pub const DEFAULT_CONF_PATH: &str = "./conf/test/config.yaml";
+
+/// Options shared by every binary that reads the config.
+#[derive(Debug, Clone, Args)]
+pub struct ConfigArgs {
+    /// Path to the YAML config file; fields can be overridden by `YTBOT_*` variables
+    #[arg(short, long, env = "YTBOT_CONFIG")]
+    pub config: Option<String>,
+
+    /// Log filter, e.g. `info` or `youtube_bot=debug`; defaults to `RUST_LOG`
+    #[arg(long, env = "YTBOT_LOG_LEVEL")]
+    pub log_level: Option<String>,
+
+    /// Log output format
+    #[arg(long, env = "YTBOT_LOG_FORMAT", value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines
+    Text,
+    /// One JSON object per line, with the fields of the enclosing spans
+    Json,
+}
+
+impl ConfigArgs {
+    /// Loads the config, falling back to environment variables only when no
+    /// path was given and the default config file does not exist.
+    pub fn load_conf(&self) -> Result<Config, conf::Error> {
+        match self.conf_path() {
+            Some(path) => conf::load_conf(&path.to_string_lossy()),
+            None => conf::load_env_conf(),
+        }
+    }
+
+    /// The config file in use, if any.
+    pub fn conf_path(&self) -> Option<PathBuf> {
+        match &self.config {
+            Some(path) => Some(PathBuf::from(path)),
+            None if Path::new(DEFAULT_CONF_PATH).exists() => Some(PathBuf::from(DEFAULT_CONF_PATH)),
+            None => None,
+        }
+    }
+
+    /// Watches the config for changes, see [`conf::watch_conf`].
+    pub fn watch_conf(&self, initial: Config) -> watch::Receiver<Arc<Config>> {
+        let args = self.clone();
+        conf::watch_conf(self.conf_path(), initial, move || args.load_conf())
+    }
+
+    /// Installs the global tracing subscriber, which also receives the
+    /// records of crates logging through `log`.
+    pub fn init_logger(&self) {
+        let filter = EnvFilter::builder().with_default_directive(LevelFilter::ERROR.into());
+        let filter = match &self.log_level {
+            Some(level) => filter.parse_lossy(level),
+            None => filter.from_env_lossy(),
+        };
+        let builder = tracing_subscriber::fmt().with_env_filter(filter);
+        match self.log_format {
+            LogFormat::Text => builder.init(),
+            LogFormat::Json => builder.json().with_current_span(false).init(),
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Fetch YouTube videos and publish them to nostr")]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Print the events that would be published as JSON, without publishing
+    /// them or writing to the database
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Run a single fetch and publish round, then exit
+    #[arg(long)]
+    pub once: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Fetch and publish videos (the default)
+    Run,
+    /// Inspect the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Manage the nostr identities of channels
+    #[command(subcommand)]
+    Channel(ChannelCommand),
+    /// Show how relays answered the events sent to them
+    Relays,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Load and validate the config, reporting every invalid field
+    Check,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ChannelCommand {
+    /// Create the identity of a configured channel now instead of on its
+    /// first poll
+    Add {
+        /// Channel name, as listed in `youtube.user_id`
+        name: String,
+        /// Existing secret key to use, as `nsec` or hex; a new key is
+        /// generated if not given
+        #[arg(long, env = "YTBOT_CHANNEL_KEY", hide_env_values = true)]
+        key: Option<String>,
+    },
+    /// Replace the key of a channel, moving its profile to the new identity
+    SetKey {
+        /// Channel name, as listed in `youtube.user_id`
+        name: String,
+        /// New secret key, as `nsec` or hex
+        #[arg(long, env = "YTBOT_CHANNEL_KEY", hide_env_values = true)]
+        key: String,
+    },
+    /// Sign the notes of a channel with a NIP-46 bunker instead of a key
+    /// held by the bot
+    Connect {
+        /// Channel name, as listed in `youtube.user_id`
+        name: String,
+        /// `bunker://` URI given by the channel owner's signer
+        #[arg(long, env = "YTBOT_BUNKER_URI", hide_env_values = true)]
+        bunker: String,
+    },
+    /// Print the keys of one or every channel as `<channel> <ncryptsec>`
+    /// lines, encrypted with a passphrase (NIP-49)
+    Export {
+        /// Channel name; every channel with a key if not given
+        name: Option<String>,
+        /// Passphrase the keys are encrypted with
+        #[arg(long, env = "YTBOT_KEY_PASSPHRASE", hide_env_values = true)]
+        passphrase: String,
+    },
+    /// Restore keys written by `channel export`, adding the channels that
+    /// have no key yet
+    Import {
+        /// File of `<channel> <ncryptsec>` lines
+        file: PathBuf,
+        /// Passphrase the keys were encrypted with
+        #[arg(long, env = "YTBOT_KEY_PASSPHRASE", hide_env_values = true)]
+        passphrase: String,
+    },
+}
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Run pending database migrations")]
+pub struct MigrationCli {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
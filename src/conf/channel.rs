@@ -0,0 +1,226 @@
+use super::Config;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use sonic_rs::Deserialize;
+use std::time::Duration;
+
+/// Poll interval used when neither the channel nor `youtube.interval` set one.
+pub const DEFAULT_INTERVAL: u64 = 600;
+/// Default of `youtube.concurrency`.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+/// Default of `nostr.max_backdate`: one day.
+pub const DEFAULT_MAX_BACKDATE: u64 = 24 * 60 * 60;
+/// Note template used when neither the channel nor `youtube.template` set one.
+pub const DEFAULT_TEMPLATE: &str = "{title}: {link}";
+
+/// An entry of `youtube.user_id`: either a bare channel name or a map with a
+/// `name` and any of the per-channel overrides.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelConfig {
+    pub name: String,
+    pub overrides: ChannelOverrides,
+}
+
+#[derive(Deserialize)]
+struct ChannelMap {
+    name: String,
+    #[serde(flatten)]
+    overrides: ChannelOverrides,
+}
+
+impl<'de> Deserialize<'de> for ChannelConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct EntryVisitor;
+
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = ChannelConfig;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a channel name or a map with a `name` field")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+                Ok(ChannelConfig {
+                    name: name.to_string(),
+                    overrides: ChannelOverrides::default(),
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let ChannelMap { name, overrides } =
+                    ChannelMap::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(ChannelConfig { name, overrides })
+            }
+        }
+
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+/// Per-channel settings; every `None` falls back to the global value.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct ChannelOverrides {
+    /// Poll interval in seconds.
+    pub interval: Option<u64>,
+    pub relays: Option<Vec<String>>,
+    pub template: Option<String>,
+    pub hashtags: Option<Vec<String>>,
+    pub language: Option<String>,
+    pub gpt: Option<bool>,
+    pub posting_window: Option<PostingWindow>,
+    pub backfill_limit: Option<usize>,
+    pub onboarding: Option<OnboardingPolicy>,
+}
+
+/// What to do with the videos already in a channel's feed when it is polled
+/// for the first time. Written as `all`, `skip`, `latest <n>` or
+/// `since <YYYY-MM-DD>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum OnboardingPolicy {
+    /// Publish the whole feed.
+    #[default]
+    All,
+    /// Mark the feed as seen without publishing anything.
+    Skip,
+    /// Publish only the newest `n` videos.
+    Latest(usize),
+    /// Publish only videos published at or after the given time.
+    Since(DateTime<Utc>),
+}
+
+impl TryFrom<String> for OnboardingPolicy {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let invalid = || {
+            format!(
+                "invalid onboarding policy `{}`, expected all, skip, latest <n> or since <YYYY-MM-DD>",
+                s
+            )
+        };
+        let mut parts = s.split_whitespace();
+        let policy = match (parts.next(), parts.next()) {
+            (Some("all"), None) => Self::All,
+            (Some("skip"), None) => Self::Skip,
+            (Some("latest"), Some(n)) => Self::Latest(n.parse().map_err(|_| invalid())?),
+            (Some("since"), Some(date)) => {
+                let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| invalid())?;
+                Self::Since(date.and_time(NaiveTime::MIN).and_utc())
+            }
+            _ => return Err(invalid()),
+        };
+        match parts.next() {
+            Some(_) => Err(invalid()),
+            None => Ok(policy),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CreatedAtPolicy {
+    /// Date notes at the time they are published.
+    #[default]
+    Now,
+    /// Date notes at the video's publish time, bounded by `max_backdate` and
+    /// never in the future.
+    Published,
+}
+
+/// A daily `HH:MM-HH:MM` time range in `publish.timezone`. The range may
+/// wrap past midnight, e.g. `22:00-06:00`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct PostingWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl PostingWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl TryFrom<String> for PostingWindow {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid posting window `{}`, expected HH:MM-HH:MM", s);
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").map_err(|_| invalid())?;
+        let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").map_err(|_| invalid())?;
+        Ok(Self { start, end })
+    }
+}
+
+/// Settings of one channel with the overrides applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelSettings {
+    pub name: String,
+    pub interval: Duration,
+    pub relays: Vec<String>,
+    pub template: String,
+    pub hashtags: Vec<String>,
+    pub language: Option<String>,
+    pub gpt: bool,
+    pub posting_window: Option<PostingWindow>,
+    pub backfill_limit: Option<usize>,
+    pub onboarding: OnboardingPolicy,
+    pub created_at: CreatedAtPolicy,
+    pub max_backdate: Duration,
+}
+
+impl Config {
+    /// Names of the configured channels, in config order.
+    pub fn channel_names(&self) -> impl Iterator<Item = &str> {
+        self.youtube.user_id.iter().map(|ch| ch.name.as_str())
+    }
+
+    pub fn channel_settings(&self, name: &str) -> Option<ChannelSettings> {
+        let channel = self.youtube.user_id.iter().find(|ch| ch.name == name)?;
+        let yt = &self.youtube;
+        let ov = &channel.overrides;
+        Some(ChannelSettings {
+            name: channel.name.clone(),
+            interval: Duration::from_secs(ov.interval.unwrap_or(yt.interval)),
+            relays: ov
+                .relays
+                .clone()
+                .unwrap_or_else(|| self.nostr.relays.clone()),
+            template: ov.template.clone().unwrap_or_else(|| yt.template.clone()),
+            hashtags: ov.hashtags.clone().unwrap_or_else(|| yt.hashtags.clone()),
+            language: ov.language.clone().or_else(|| yt.language.clone()),
+            gpt: ov.gpt.unwrap_or(yt.gpt),
+            posting_window: ov.posting_window.or(yt.posting_window),
+            backfill_limit: ov.backfill_limit.or(yt.backfill_limit),
+            onboarding: ov.onboarding.unwrap_or(yt.onboarding),
+            created_at: self.nostr.created_at,
+            max_backdate: Duration::from_secs(self.nostr.max_backdate),
+        })
+    }
+}
+
+pub(crate) fn default_interval() -> u64 {
+    DEFAULT_INTERVAL
+}
+
+pub(crate) fn default_concurrency() -> usize {
+    DEFAULT_CONCURRENCY
+}
+
+pub(crate) fn default_max_backdate() -> u64 {
+    DEFAULT_MAX_BACKDATE
+}
+
+pub(crate) fn default_template() -> String {
+    DEFAULT_TEMPLATE.to_string()
+}
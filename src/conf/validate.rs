@@ -0,0 +1,254 @@
+use super::{Config, PostingWindow, Rate};
+use crate::nostr::nip05_name;
+use nostr_sdk::bip39::Mnemonic;
+use nostr_sdk::Url;
+use std::collections::{HashMap, HashSet};
+
+/// Upper bound of `maxResults` accepted by the YouTube search API.
+pub const MAX_COUNT: u32 = 50;
+/// Upper bound of `nostr.max_backdate`: ten years, well before nostr existed.
+pub const MAX_BACKDATE: u64 = 10 * 365 * 24 * 60 * 60;
+
+/// A problem with a single config field, e.g. `nostr.relays[3]: not a websocket URL`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub path: String,
+    pub message: String,
+}
+
+impl ValidationError {
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl Config {
+    /// Checks the values serde cannot, returning every problem found.
+    pub fn validate(&self) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+
+        if self.youtube.api_key.trim().is_empty() {
+            errors.push(ValidationError::new("youtube.api_key", "must not be empty"));
+        }
+        if self.youtube.count == 0 || self.youtube.count > MAX_COUNT {
+            errors.push(ValidationError::new(
+                "youtube.count",
+                format!("must be between 1 and {}", MAX_COUNT),
+            ));
+        }
+        if self.youtube.concurrency == 0 {
+            errors.push(ValidationError::new(
+                "youtube.concurrency",
+                "must be positive",
+            ));
+        }
+        if self.youtube.user_id.is_empty() {
+            errors.push(ValidationError::new(
+                "youtube.user_id",
+                "must list at least one channel",
+            ));
+        }
+        if self.youtube.interval == 0 {
+            errors.push(ValidationError::new("youtube.interval", "must be positive"));
+        }
+        validate_hashtags("youtube.hashtags", &self.youtube.hashtags, &mut errors);
+        if let Some(language) = &self.youtube.language {
+            validate_language("youtube.language", language, &mut errors);
+        }
+        if self.youtube.gpt && self.gpt.is_none() {
+            errors.push(ValidationError::new("youtube.gpt", "requires gpt.api_key"));
+        }
+        if let Some(window) = &self.youtube.posting_window {
+            validate_window("youtube.posting_window", window, &mut errors);
+        }
+
+        let mut channels = HashSet::new();
+        for (i, channel) in self.youtube.user_id.iter().enumerate() {
+            let path = format!("youtube.user_id[{}]", i);
+            if channel.name.trim().is_empty() {
+                errors.push(ValidationError::new(path.clone(), "must not be empty"));
+            } else if !channels.insert(&channel.name) {
+                errors.push(ValidationError::new(path.clone(), "duplicate channel"));
+            }
+
+            let overrides = &channel.overrides;
+            if overrides.interval == Some(0) {
+                errors.push(ValidationError::new(
+                    format!("{}.interval", path),
+                    "must be positive",
+                ));
+            }
+            if let Some(relays) = &overrides.relays {
+                validate_relays(&format!("{}.relays", path), relays, &mut errors);
+            }
+            if let Some(hashtags) = &overrides.hashtags {
+                validate_hashtags(&format!("{}.hashtags", path), hashtags, &mut errors);
+            }
+            if let Some(language) = &overrides.language {
+                validate_language(&format!("{}.language", path), language, &mut errors);
+            }
+            if let Some(window) = &overrides.posting_window {
+                validate_window(&format!("{}.posting_window", path), window, &mut errors);
+            }
+            if overrides.gpt == Some(true) && self.gpt.is_none() {
+                errors.push(ValidationError::new(
+                    format!("{}.gpt", path),
+                    "requires gpt.api_key",
+                ));
+            }
+        }
+
+        if let Some(gpt) = &self.gpt {
+            if gpt.api_key.trim().is_empty() {
+                errors.push(ValidationError::new("gpt.api_key", "must not be empty"));
+            }
+        }
+
+        validate_relays("nostr.relays", &self.nostr.relays, &mut errors);
+        if self.nostr.max_backdate > MAX_BACKDATE {
+            errors.push(ValidationError::new(
+                "nostr.max_backdate",
+                format!("must be at most {} seconds", MAX_BACKDATE),
+            ));
+        }
+        if let Some(domain) = &self.nostr.nip05_domain {
+            match Url::parse(&format!("https://{}/", domain)) {
+                Ok(url) if url.host_str() == Some(domain.as_str()) && url.path() == "/" => (),
+                _ => errors.push(ValidationError::new(
+                    "nostr.nip05_domain",
+                    "not a domain name",
+                )),
+            }
+            let mut names = HashMap::new();
+            for (i, channel) in self.youtube.user_id.iter().enumerate() {
+                let name = nip05_name(&channel.name);
+                match names.insert(name.clone(), &channel.name) {
+                    // duplicate channels are reported above
+                    Some(other) if *other != channel.name => errors.push(ValidationError::new(
+                        format!("youtube.user_id[{}]", i),
+                        format!("NIP-05 name `{}` is also used by {}", name, other),
+                    )),
+                    _ => (),
+                }
+            }
+        }
+        if let Some(mnemonic) = &self.nostr.mnemonic {
+            if let Err(e) = Mnemonic::parse(mnemonic) {
+                errors.push(ValidationError::new(
+                    "nostr.mnemonic",
+                    format!("not a BIP-39 mnemonic: {}", e),
+                ));
+            }
+        }
+
+        validate_rate(
+            "publish.identity_rate",
+            &self.publish.identity_rate,
+            &mut errors,
+        );
+        if let Some(rate) = &self.publish.global_rate {
+            validate_rate("publish.global_rate", rate, &mut errors);
+        }
+        if let Some(quiet) = &self.publish.quiet_hours {
+            validate_window("publish.quiet_hours", quiet, &mut errors);
+        }
+        if self.publish.max_posts_per_day == Some(0) {
+            errors.push(ValidationError::new(
+                "publish.max_posts_per_day",
+                "must be positive",
+            ));
+        }
+
+        match self.postgres.dsn.strip_prefix(data::SQLITE_SCHEME) {
+            Some(path) if path.trim().is_empty() => errors.push(ValidationError::new(
+                "postgres.dsn",
+                "missing the SQLite database path",
+            )),
+            // every connection would open its own empty database
+            Some(path) if path.trim() == ":memory:" => errors.push(ValidationError::new(
+                "postgres.dsn",
+                "in-memory SQLite databases are not shared between workers",
+            )),
+            Some(_) => (),
+            None => match Url::parse(&self.postgres.dsn) {
+                Ok(url) if matches!(url.scheme(), "postgres" | "postgresql") => (),
+                Ok(_) => errors.push(ValidationError::new(
+                    "postgres.dsn",
+                    "not a postgres:// or sqlite:// URL",
+                )),
+                Err(e) => errors.push(ValidationError::new("postgres.dsn", e.to_string())),
+            },
+        }
+
+        errors
+    }
+}
+
+fn validate_relays(path: &str, relays: &[String], errors: &mut Vec<ValidationError>) {
+    if relays.is_empty() {
+        errors.push(ValidationError::new(path, "must list at least one relay"));
+    }
+    let mut seen = HashSet::new();
+    for (i, relay) in relays.iter().enumerate() {
+        let path = format!("{}[{}]", path, i);
+        match Url::parse(relay) {
+            Ok(url) if matches!(url.scheme(), "ws" | "wss") && url.host_str().is_some() => {
+                if !seen.insert(url) {
+                    errors.push(ValidationError::new(path, "duplicate relay"));
+                }
+            }
+            _ => errors.push(ValidationError::new(path, "not a websocket URL")),
+        }
+    }
+}
+
+fn validate_hashtags(path: &str, hashtags: &[String], errors: &mut Vec<ValidationError>) {
+    for (i, tag) in hashtags.iter().enumerate() {
+        if tag.is_empty() || tag.starts_with('#') || tag.contains(char::is_whitespace) {
+            errors.push(ValidationError::new(
+                format!("{}[{}]", path, i),
+                "must be a single word without the leading #",
+            ));
+        }
+    }
+}
+
+fn validate_language(path: &str, language: &str, errors: &mut Vec<ValidationError>) {
+    if language.len() != 2 || !language.chars().all(|c| c.is_ascii_lowercase()) {
+        errors.push(ValidationError::new(path, "not an ISO 639-1 code"));
+    }
+}
+
+/// A window whose start and end are equal would be empty, so a channel
+/// would never post, or quiet hours would never end.
+fn validate_window(path: &str, window: &PostingWindow, errors: &mut Vec<ValidationError>) {
+    if window.start == window.end {
+        errors.push(ValidationError::new(path, "start and end must differ"));
+    }
+}
+
+fn validate_rate(path: &str, rate: &Rate, errors: &mut Vec<ValidationError>) {
+    if rate.burst == 0 {
+        errors.push(ValidationError::new(
+            format!("{}.burst", path),
+            "must be positive",
+        ));
+    }
+    if rate.every == 0 {
+        errors.push(ValidationError::new(
+            format!("{}.every", path),
+            "must be positive",
+        ));
+    }
+}
@@ -0,0 +1,79 @@
+use super::{Config, Error};
+use std::future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::sync::watch;
+
+/// How often the config file is checked for modifications.
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Reloads the config whenever `path` is modified or the process receives
+/// SIGHUP, publishing every valid config that differs from the current one.
+///
+/// A config that fails to load or validate is logged and ignored, so the bot
+/// keeps running with the last good one.
+pub fn watch_conf<F>(
+    path: Option<PathBuf>,
+    initial: Config,
+    load: F,
+) -> watch::Receiver<Arc<Config>>
+where
+    F: Fn() -> Result<Config, Error> + Send + 'static,
+{
+    let (tx, rx) = watch::channel(Arc::new(initial));
+
+    tokio::spawn(async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => Some(hangup),
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGHUP: {}", e);
+                None
+            }
+        };
+        let mut modified = path.as_deref().and_then(modified_time);
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let current = path.as_deref().and_then(modified_time);
+                    if current == modified {
+                        continue;
+                    }
+                    modified = current;
+                    tracing::info!("Config file changed, reloading");
+                }
+                _ = recv_signal(&mut hangup) => tracing::info!("Received SIGHUP, reloading config"),
+                _ = tx.closed() => break,
+            }
+
+            match load() {
+                Ok(conf) => {
+                    if **tx.borrow() != conf {
+                        tx.send_replace(Arc::new(conf));
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Failed to reload config, keeping the current one: {}", e)
+                }
+            }
+        }
+    });
+
+    rx
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+async fn recv_signal(signal: &mut Option<Signal>) {
+    match signal {
+        Some(signal) => {
+            signal.recv().await;
+        }
+        None => future::pending().await,
+    }
+}
@@ -1,5 +1,69 @@
 use chatgpt::client::ChatGPT;
 
+#[derive(Debug)]
+pub enum Error {
+    ChatGPT(chatgpt::err::Error),
+}
+
+impl From<chatgpt::err::Error> for Error {
+    fn from(e: chatgpt::err::Error) -> Self {
+        Self::ChatGPT(e)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::ChatGPT(e) => write!(f, "ChatGPT error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::ChatGPT(e) => Some(e),
+        }
+    }
+}
+
+impl Error {
+    /// Request and IO failures; chatgpt_rs uses its own reqwest version, so
+    /// the request error is classified by its status here.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::ChatGPT(chatgpt::err::Error::ClientError(e)) => match e.status() {
+                Some(status) => status.is_server_error() || status.as_u16() == 429,
+                None => e.is_timeout() || e.is_connect() || e.is_request(),
+            },
+            Self::ChatGPT(chatgpt::err::Error::IOError(_)) => true,
+            Self::ChatGPT(_) => false,
+        }
+    }
+}
+
 pub struct ChatGPTClient {
     client: ChatGPT,
 }
+
+impl ChatGPTClient {
+    pub fn new(api_key: &str) -> Result<Self, Error> {
+        let client = ChatGPT::new(api_key)?;
+        Ok(Self { client })
+    }
+
+    /// Rewrites a note announcing a video, keeping its links intact.
+    pub async fn rewrite_note(&self, note: &str, language: Option<&str>) -> Result<String, Error> {
+        let language = match language {
+            Some(lang) => format!(" in the language with ISO 639-1 code `{}`", lang),
+            None => String::new(),
+        };
+        let prompt = format!(
+            "Rewrite the following social media post announcing a new YouTube video{} \
+             so it is short and engaging. Keep every URL unchanged and reply with the post only.\n\n{}",
+            language, note
+        );
+        let response = self.client.send_message(prompt).await?;
+        Ok(response.message().content.trim().to_string())
+    }
+}
@@ -0,0 +1,328 @@
+use crate::bot::StatusBoard;
+use crate::conf::Config;
+use crate::metrics;
+use crate::nostr::{nip05_name, RelayProbe};
+use crate::Error;
+use data::{DbConnection, RelayHealth};
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{HeaderValue, ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use nostr_sdk::PublicKey;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+
+/// How long `/readyz` waits for the database.
+const DB_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Path of the NIP-05 document.
+const NIP05_PATH: &str = "/.well-known/nostr.json";
+
+/// Serves `/healthz`, `/readyz`, `/status`, `/status/relays` and `/metrics`
+/// for orchestrators and Prometheus, and the NIP-05 names of the channels'
+/// identities.
+pub struct HttpServer {
+    listener: TcpListener,
+    state: State,
+}
+
+struct State {
+    status: Arc<StatusBoard>,
+    updates: watch::Receiver<Arc<Config>>,
+    /// Not kept in dry-run mode, which contacts no relay.
+    relays: Option<RelayProbe>,
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    ready: bool,
+    database: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    database_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    relays_connected: Option<usize>,
+}
+
+/// How a relay answered the events sent to it.
+#[derive(Serialize)]
+struct RelayStatus {
+    url: String,
+    demoted: bool,
+    sent: i32,
+    accepted: i32,
+    rejected: i32,
+    failed: i32,
+    consecutive_failures: i32,
+    acceptance_rate: Option<f64>,
+    average_latency_ms: Option<u128>,
+    last_error: Option<String>,
+    last_attempt: i64,
+}
+
+impl From<RelayHealth> for RelayStatus {
+    fn from(relay: RelayHealth) -> Self {
+        Self {
+            demoted: relay.is_demoted(),
+            acceptance_rate: relay.acceptance_rate(),
+            average_latency_ms: relay.average_latency().map(|latency| latency.as_millis()),
+            url: relay.url,
+            sent: relay.sent,
+            accepted: relay.accepted,
+            rejected: relay.rejected,
+            failed: relay.failed,
+            consecutive_failures: relay.consecutive_failures,
+            last_error: relay.last_error,
+            last_attempt: relay.last_attempt,
+        }
+    }
+}
+
+/// The NIP-05 `nostr.json` document: hex public keys by name, and the
+/// relays of each key.
+#[derive(Default, Serialize)]
+struct Nip05Document {
+    names: BTreeMap<String, String>,
+    relays: BTreeMap<String, Vec<String>>,
+}
+
+impl HttpServer {
+    pub async fn bind(
+        listen: SocketAddr,
+        status: Arc<StatusBoard>,
+        updates: watch::Receiver<Arc<Config>>,
+    ) -> Result<Self, Error> {
+        let listener = TcpListener::bind(listen).await?;
+        tracing::info!("Listening on http://{}", listen);
+        let state = State {
+            status,
+            updates,
+            relays: Some(RelayProbe::new()),
+        };
+        Ok(Self { listener, state })
+    }
+
+    /// In dry-run mode no relay is probed, and readiness only depends on
+    /// the database.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        if dry_run {
+            self.state.relays = None;
+        }
+        self
+    }
+
+    /// The address the server listens on, e.g. to find the port chosen
+    /// for port 0.
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub async fn run(self) {
+        let state = Arc::new(self.state);
+        if state.relays.is_some() {
+            tokio::spawn(probe_relays(state.clone()));
+        }
+        loop {
+            let (stream, peer) = match self.listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    tracing::warn!("Failed to accept HTTP connection: {}", e);
+                    continue;
+                }
+            };
+            let state = state.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |req| handle(req, state.clone()));
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    tracing::debug!("HTTP connection from {} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+}
+
+/// Keeps the relay probe connected to the relays of every channel.
+async fn probe_relays(state: Arc<State>) {
+    let probe = match &state.relays {
+        Some(probe) => probe,
+        None => return,
+    };
+    let mut updates = state.updates.clone();
+    loop {
+        let conf = updates.borrow_and_update().clone();
+        let relays: HashSet<String> = conf
+            .channel_names()
+            .filter_map(|name| conf.channel_settings(name))
+            .flat_map(|settings| settings.relays)
+            .collect();
+        probe.set_relays(&relays).await;
+        if updates.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn handle(
+    req: Request<Incoming>,
+    state: Arc<State>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/healthz") => json(StatusCode::OK, &Health { status: "ok" }),
+        (&Method::GET, "/readyz") => {
+            let readiness = state.readiness().await;
+            let code = match readiness.ready {
+                true => StatusCode::OK,
+                false => StatusCode::SERVICE_UNAVAILABLE,
+            };
+            json(code, &readiness)
+        }
+        (&Method::GET, "/status") => json(StatusCode::OK, &state.status.snapshot()),
+        (&Method::GET, "/status/relays") => match state.relay_health().await {
+            Ok(relays) => json(StatusCode::OK, &relays),
+            Err(e) => {
+                tracing::error!("Failed to load relay health: {}", e);
+                empty(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        },
+        (&Method::GET, NIP05_PATH) => {
+            let name = req.uri().query().and_then(|query| {
+                form_urlencoded::parse(query.as_bytes())
+                    .find(|(key, _)| key == "name")
+                    .map(|(_, name)| name.to_lowercase())
+            });
+            match state.nip05(name.as_deref()).await {
+                Ok(document) => {
+                    let mut response = json(StatusCode::OK, &document);
+                    // web clients fetch the document from other origins
+                    response
+                        .headers_mut()
+                        .insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+                    response
+                }
+                Err(e) => {
+                    tracing::error!("Failed to load NIP-05 names: {}", e);
+                    empty(StatusCode::INTERNAL_SERVER_ERROR)
+                }
+            }
+        }
+        (&Method::GET, "/metrics") => {
+            let mut response = Response::new(Full::new(Bytes::from(metrics::gather())));
+            response.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static(metrics::CONTENT_TYPE),
+            );
+            response
+        }
+        (_, "/healthz" | "/readyz" | "/status" | "/status/relays" | "/metrics" | NIP05_PATH) => {
+            empty(StatusCode::METHOD_NOT_ALLOWED)
+        }
+        _ => empty(StatusCode::NOT_FOUND),
+    };
+    Ok(response)
+}
+
+impl State {
+    /// Ready when the database answers and at least one relay is connected,
+    /// or only when the database answers if no relay is probed.
+    async fn readiness(&self) -> Readiness {
+        let dsn = self.updates.borrow().postgres.dsn.clone();
+        let ping = tokio::task::spawn_blocking(move || DbConnection::new(&dsn)?.ping());
+        let database_error = match tokio::time::timeout(DB_TIMEOUT, ping).await {
+            Ok(Ok(Ok(()))) => None,
+            Ok(Ok(Err(e))) => Some(e.to_string()),
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(_) => Some("timed out".to_string()),
+        };
+        let relays_connected = match &self.relays {
+            Some(probe) => Some(probe.connected().await),
+            None => None,
+        };
+        Readiness {
+            ready: database_error.is_none()
+                && relays_connected.is_none_or(|connected| connected > 0),
+            database: database_error.is_none(),
+            database_error,
+            relays_connected,
+        }
+    }
+
+    /// The health of every relay events were sent to.
+    async fn relay_health(&self) -> Result<Vec<RelayStatus>, Error> {
+        let dsn = self.updates.borrow().postgres.dsn.clone();
+        let relays =
+            tokio::task::spawn_blocking(move || DbConnection::new(&dsn)?.load_relay_health())
+                .await
+                .map_err(|e| Error::Custom(e.to_string()))??;
+        Ok(relays.into_iter().map(RelayStatus::from).collect())
+    }
+
+    /// The NIP-05 names of the configured channels that have a key, or only
+    /// `name` if given.
+    async fn nip05(&self, name: Option<&str>) -> Result<Nip05Document, Error> {
+        let conf = self.updates.borrow().clone();
+        let dsn = conf.postgres.dsn.clone();
+        let keys = tokio::task::spawn_blocking(move || DbConnection::new(&dsn)?.public_keys())
+            .await
+            .map_err(|e| Error::Custom(e.to_string()))??;
+        let mut document = Nip05Document::default();
+        for (channel, public_key) in keys {
+            let settings = match conf.channel_settings(&channel) {
+                Some(settings) => settings,
+                None => continue,
+            };
+            let nip05 = nip05_name(&channel);
+            if name.is_some_and(|name| name != nip05) {
+                continue;
+            }
+            let public_key = match PublicKey::parse(&public_key) {
+                Ok(public_key) => public_key.to_hex(),
+                Err(e) => {
+                    tracing::warn!("Invalid public key of channel {}: {}", channel, e);
+                    continue;
+                }
+            };
+            document.relays.insert(public_key.clone(), settings.relays);
+            document.names.insert(nip05, public_key);
+        }
+        Ok(document)
+    }
+}
+
+fn json<T: Serialize>(code: StatusCode, body: &T) -> Response<Full<Bytes>> {
+    match serde_json::to_vec(body) {
+        Ok(body) => {
+            let mut response = Response::new(Full::new(Bytes::from(body)));
+            *response.status_mut() = code;
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            response
+        }
+        Err(e) => {
+            tracing::error!("Failed to serialize response: {}", e);
+            empty(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn empty(code: StatusCode) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::new()));
+    *response.status_mut() = code;
+    response
+}
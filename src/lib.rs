@@ -1,32 +1,84 @@
 mod api;
+mod bot;
+mod cli;
 mod conf;
 mod gpt;
+mod http;
+mod limit;
+mod metrics;
 mod nostr;
 
+use chrono::Utc;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::time::Duration;
-use tokio::time;
 
 pub use api::RssFetcher;
+pub use api::VideoInfo;
 pub use api::YoutubeFetcher;
+pub use bot::Bot;
+pub use bot::StatusBoard;
+pub use cli::ChannelCommand;
+pub use cli::Cli;
+pub use cli::Command;
+pub use cli::ConfigArgs;
+pub use cli::ConfigCommand;
+pub use cli::MigrationCli;
 pub use conf::load_conf;
+pub use conf::ChannelSettings;
 pub use conf::Config;
-use data::DbConnection;
+pub use conf::OnboardingPolicy;
+pub use data::DbConnection;
+pub use data::MemoryStore;
+pub use data::RelayHealth;
+pub use data::RelayOutcome;
+pub use data::Store;
+pub use data::{DEMOTED_RETRY, DEMOTE_AFTER};
+pub use gpt::ChatGPTClient;
+pub use http::HttpServer;
+pub use limit::PostLimiter;
+pub use nostr::nip05_name;
+pub use nostr::Error as NostrError;
+pub use nostr::Note;
 pub use nostr::NotePublisher;
+pub use nostr::Publisher;
+pub use nostr::RelayReport;
 use nostr_sdk::SecretKey;
 
+use nostr_sdk::hashes::{sha256, Hash};
+use nostr_sdk::nips::nip06::{self, FromMnemonic};
+use nostr_sdk::nips::nip46::NostrConnectURI;
+use nostr_sdk::nips::nip49::{self, EncryptedSecretKey, KeySecurity};
+use nostr_sdk::signer::Nip46Signer;
+use nostr_sdk::Event;
+use nostr_sdk::EventBuilder;
+use nostr_sdk::EventId;
+use nostr_sdk::FromBech32;
+use nostr_sdk::JsonUtil;
 use nostr_sdk::Keys;
+use nostr_sdk::Metadata;
+use nostr_sdk::NostrSigner;
+use nostr_sdk::PublicKey;
 use nostr_sdk::ToBech32;
+use nostr_sdk::UnsignedEvent;
 
 #[derive(Debug)]
 pub enum Error {
     DbError(data::Error),
     ConfigError(conf::Error),
     NIP19(nostr_sdk::nips::nip19::Error),
+    /// A key could not be derived from the mnemonic.
+    NIP06(nip06::Error),
+    /// A key could not be encrypted, or an encrypted key is malformed.
+    NIP49(nip49::Error),
+    Event(nostr_sdk::event::builder::Error),
     Nostr(nostr_sdk::key::Error),
     NostrWrapper(nostr::Error),
     Rss(api::RssError),
     Youtube(api::YoutubeError),
+    Gpt(gpt::Error),
+    /// Signing failed, e.g. a bunker did not answer.
+    Signer(nostr_sdk::signer::Error),
     IO(std::io::Error),
     Custom(String),
 }
@@ -55,6 +107,24 @@ impl From<api::YoutubeError> for Error {
     }
 }
 
+impl From<gpt::Error> for Error {
+    fn from(e: gpt::Error) -> Self {
+        Self::Gpt(e)
+    }
+}
+
+impl From<nostr_sdk::signer::Error> for Error {
+    fn from(e: nostr_sdk::signer::Error) -> Self {
+        Self::Signer(e)
+    }
+}
+
+impl From<nostr_sdk::signer::nip46::Error> for Error {
+    fn from(e: nostr_sdk::signer::nip46::Error) -> Self {
+        Self::Signer(e.into())
+    }
+}
+
 impl From<nostr_sdk::key::Error> for Error {
     fn from(e: nostr_sdk::key::Error) -> Self {
         Self::Nostr(e)
@@ -67,6 +137,24 @@ impl From<nostr_sdk::nips::nip19::Error> for Error {
     }
 }
 
+impl From<nip06::Error> for Error {
+    fn from(e: nip06::Error) -> Self {
+        Self::NIP06(e)
+    }
+}
+
+impl From<nip49::Error> for Error {
+    fn from(e: nip49::Error) -> Self {
+        Self::NIP49(e)
+    }
+}
+
+impl From<nostr_sdk::event::builder::Error> for Error {
+    fn from(e: nostr_sdk::event::builder::Error) -> Self {
+        Self::Event(e)
+    }
+}
+
 impl From<data::Error> for Error {
     fn from(e: data::Error) -> Self {
         Self::DbError(e)
@@ -85,24 +173,137 @@ impl std::fmt::Display for Error {
             Self::DbError(e) => write!(f, "Database error: {}", e),
             Self::ConfigError(e) => write!(f, "Config error: {}", e),
             Self::NIP19(e) => write!(f, "Nip19 error: {}", e),
+            Self::NIP06(e) => write!(f, "Nip06 error: {}", e),
+            Self::NIP49(e) => write!(f, "Nip49 error: {}", e),
+            Self::Event(e) => write!(f, "Event error: {}", e),
             Self::Custom(e) => write!(f, "Custom error: {}", e),
             Self::Nostr(e) => write!(f, "Nostr error: {}", e),
             Self::Rss(e) => write!(f, "Rss error: {}", e),
             Self::Youtube(e) => write!(f, "Youtube error: {}", e),
+            Self::Gpt(e) => write!(f, "Gpt error: {}", e),
+            Self::Signer(e) => write!(f, "Signer error: {}", e),
             Self::NostrWrapper(e) => write!(f, "NostrWrapper error: {}", e),
             Self::IO(e) => write!(f, "IO error: {}", e),
         }
     }
 }
 
-pub struct App {
-    db: DbConnection,
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::DbError(e) => Some(e),
+            Self::ConfigError(e) => Some(e),
+            Self::NIP19(e) => Some(e),
+            Self::NIP06(e) => Some(e),
+            Self::NIP49(e) => Some(e),
+            Self::Event(e) => Some(e),
+            Self::Nostr(e) => Some(e),
+            Self::NostrWrapper(e) => Some(e),
+            Self::Rss(e) => Some(e),
+            Self::Youtube(e) => Some(e),
+            Self::Gpt(e) => Some(e),
+            Self::Signer(e) => Some(e),
+            Self::IO(e) => Some(e),
+            Self::Custom(_) => None,
+        }
+    }
+}
+
+impl Error {
+    /// Whether the operation may succeed if retried later, e.g. after a
+    /// network failure, a rate limit or a lost database connection.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::DbError(e) => e.is_transient(),
+            Self::NostrWrapper(e) => e.is_transient(),
+            Self::Rss(e) => e.is_transient(),
+            Self::Youtube(e) => e.is_transient(),
+            Self::Gpt(e) => e.is_transient(),
+            Self::IO(_) | Self::Signer(_) => true,
+            Self::ConfigError(_)
+            | Self::NIP19(_)
+            | Self::NIP06(_)
+            | Self::NIP49(_)
+            | Self::Event(_)
+            | Self::Nostr(_)
+            | Self::Custom(_) => false,
+        }
+    }
+}
+
+pub struct App<S = DbConnection, P = NotePublisher> {
+    db: S,
+    publisher: P,
+    dry_run: bool,
+    dry_run_keys: HashMap<String, Keys>,
+    dry_run_seen: HashSet<String>,
+    mnemonic: Option<String>,
+    nip05_domain: Option<String>,
 }
 
 impl App {
     pub fn new(dsn: &str) -> Result<Self, Error> {
         let db = DbConnection::new(dsn)?;
-        Ok(Self { db })
+        Ok(Self::with_store(db))
+    }
+}
+
+impl<S: Store> App<S> {
+    pub fn with_store(db: S) -> Self {
+        Self {
+            db,
+            publisher: NotePublisher::new(),
+            dry_run: false,
+            dry_run_keys: HashMap::new(),
+            dry_run_seen: HashSet::new(),
+            mnemonic: None,
+            nip05_domain: None,
+        }
+    }
+}
+
+impl<S: Store, P: Publisher> App<S, P> {
+    /// Sends events through `publisher` instead of the default
+    /// [`NotePublisher`].
+    pub fn with_publisher<Q: Publisher>(self, publisher: Q) -> App<S, Q> {
+        App {
+            db: self.db,
+            publisher,
+            dry_run: self.dry_run,
+            dry_run_keys: self.dry_run_keys,
+            dry_run_seen: self.dry_run_seen,
+            mnemonic: self.mnemonic,
+            nip05_domain: self.nip05_domain,
+        }
+    }
+
+    /// Gives the store back, e.g. to inspect it once the app is done.
+    pub fn into_store(self) -> S {
+        self.db
+    }
+
+    /// In dry-run mode the app still reads the database and fetches feeds,
+    /// but never writes users or videos, and `publish` prints the signed
+    /// event as JSON instead of sending it. New channels get a key that is
+    /// not stored.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Derives the keys of new channels from `mnemonic` with [`derive_key`]
+    /// instead of generating random ones. Channels that already have a key
+    /// keep it.
+    pub fn mnemonic(mut self, mnemonic: Option<String>) -> Self {
+        self.mnemonic = mnemonic;
+        self
+    }
+
+    /// Claims the NIP-05 identifier `<name>@<domain>` in the profiles of
+    /// the channels, see [`nip05_name`].
+    pub fn nip05_domain(mut self, domain: Option<String>) -> Self {
+        self.nip05_domain = domain;
+        self
     }
 
     pub async fn check_user(
@@ -121,42 +322,292 @@ impl App {
             };
             Ok(chid)
         } else {
-            let youtube = YoutubeFetcher::new(api, channel_name, count);
-            let channel_id = youtube.get_channel_id().await?;
-            let user_info = youtube.get_user_info().await?;
-            let user_name = user_info.user_name;
-            let avatar_url = user_info.avatar_link;
-
-            let key = new_key()?;
-            let private_key = key.secret_key;
-            let public_key = key.public_key;
-            self.db
-                .add_user(
-                    &user_name,
-                    &avatar_url,
-                    &public_key,
-                    &private_key,
-                    channel_name,
-                    &channel_id,
-                )
+            self.create_user(channel_name, api, count, None).await
+        }
+    }
+
+    /// Adds the identity of a channel ahead of its first poll, with `keys`
+    /// or a new key. Fails if the channel already has one, see
+    /// [`App::replace_key`], or if another channel uses `keys`.
+    pub async fn add_channel(
+        &mut self,
+        channel_name: &str,
+        api: &str,
+        count: u32,
+        keys: Option<Keys>,
+    ) -> Result<String, Error> {
+        if self.db.user_exists(channel_name).await? {
+            return Err(Error::Custom(format!(
+                "Channel {} already has a key",
+                channel_name
+            )));
+        }
+        // before asking YouTube about the channel
+        if let Some(keys) = &keys {
+            self.check_key_unused(channel_name, keys).await?;
+        }
+        self.create_user(channel_name, api, count, keys).await
+    }
+
+    async fn create_user(
+        &mut self,
+        channel_name: &str,
+        api: &str,
+        count: u32,
+        keys: Option<Keys>,
+    ) -> Result<String, Error> {
+        let youtube = YoutubeFetcher::new(api, channel_name, count);
+        let channel_id = youtube.get_channel_id().await?;
+        let keys = match (keys, &self.mnemonic) {
+            (Some(keys), _) => keys,
+            (None, Some(mnemonic)) => derive_key(mnemonic, &channel_id)?,
+            (None, None) => Keys::generate(),
+        };
+        self.check_key_unused(channel_name, &keys).await?;
+        if self.dry_run {
+            tracing::info!("Dry run, not adding user {}", channel_name);
+            self.dry_run_keys
+                .entry(channel_name.to_string())
+                .or_insert(keys);
+            return Ok(channel_id);
+        }
+        let user_info = youtube.get_user_info().await?;
+        let user_name = user_info.user_name;
+        let avatar_url = user_info.avatar_link;
+
+        let key = my_key(&keys)?;
+        let private_key = key.secret_key;
+        let public_key = key.public_key;
+        self.db
+            .add_user(
+                &user_name,
+                &avatar_url,
+                &public_key,
+                &private_key,
+                channel_name,
+                &channel_id,
+            )
+            .await?;
+        Ok(channel_id)
+    }
+
+    /// Switches a channel to `keys`. The channel's metadata is published
+    /// under the new key first, so the key is only replaced once a relay has
+    /// the profile; the old identity's profile then points to the new one.
+    /// A channel signing through a bunker goes back to signing locally.
+    pub async fn replace_key(
+        &mut self,
+        channel_name: &str,
+        keys: &Keys,
+        relays: &[String],
+    ) -> Result<(), Error> {
+        let metadata = self.channel_metadata(channel_name).await?;
+        if self.public_key(channel_name).await? == keys.public_key() {
+            return Err(Error::Custom(format!(
+                "Channel {} already uses this key",
+                channel_name
+            )));
+        }
+        self.check_key_unused(channel_name, keys).await?;
+        let event = EventBuilder::metadata(&metadata).to_event(keys)?;
+        if self.dry_run {
+            println!("{}", event.as_json());
+            return Ok(());
+        }
+        let old = self.signer(channel_name).await?;
+        let replaced = self
+            .move_identity(channel_name, &old, keys, &event, metadata, relays)
+            .await;
+        close_signer(old).await;
+        replaced
+    }
+
+    async fn move_identity(
+        &mut self,
+        channel_name: &str,
+        old: &NostrSigner,
+        keys: &Keys,
+        event: &Event,
+        metadata: Metadata,
+        relays: &[String],
+    ) -> Result<(), Error> {
+        self.send(relays, event).await?;
+
+        let key = my_key(keys)?;
+        self.db
+            .set_keys(channel_name, &key.public_key, &key.secret_key)
+            .await?;
+        self.db.set_bunker(channel_name, None).await?;
+        self.db.set_relay_list(channel_name, None).await?;
+
+        // the NIP-05 identifier now belongs to the new key
+        let mut moved = metadata.about(format!("Moved to nostr:{}", key.public_key));
+        moved.nip05 = None;
+        let moved = old.sign_event_builder(EventBuilder::metadata(&moved)).await;
+        let sent = match moved {
+            Ok(event) => self.send(relays, &event).await,
+            Err(e) => Err(Error::from(e)),
+        };
+        if let Err(e) = sent {
+            tracing::warn!(error = %e, "Failed to point the old identity to the new key");
+        }
+        Ok(())
+    }
+
+    /// Makes a channel sign through the NIP-46 bunker at `uri` instead of
+    /// holding its key. The channel's key is kept as the client key the
+    /// bunker authorizes, and the channel's identity becomes the bunker's.
+    /// The profile is published through the bunker before anything is
+    /// stored, so a bunker that does not sign leaves the channel unchanged.
+    /// In dry-run mode the bunker is not contacted: the profile is printed
+    /// unsigned for the signer named in the URI. Returns the new identity.
+    pub async fn connect_bunker(
+        &mut self,
+        channel_name: &str,
+        uri: &str,
+        relays: &[String],
+    ) -> Result<PublicKey, Error> {
+        let metadata = self.channel_metadata(channel_name).await?;
+        let client_keys = self.local_keys(channel_name).await?;
+        if self.dry_run {
+            let public_key = bunker_uri(uri)?.signer_public_key().ok_or_else(|| {
+                Error::Custom("A dry run needs a bunker:// URI naming its signer".to_string())
+            })?;
+            let event = EventBuilder::metadata(&metadata).to_unsigned_event(public_key);
+            print_unsigned(event);
+            return Ok(public_key);
+        }
+        let remote = NostrSigner::nip46(remote_signer(uri, client_keys).await?);
+        let connected = self
+            .use_bunker(channel_name, &remote, uri, metadata, relays)
+            .await;
+        close_signer(remote).await;
+        connected
+    }
+
+    async fn use_bunker(
+        &mut self,
+        channel_name: &str,
+        remote: &NostrSigner,
+        uri: &str,
+        metadata: Metadata,
+        relays: &[String],
+    ) -> Result<PublicKey, Error> {
+        let public_key = remote.public_key().await?;
+        let event = remote
+            .sign_event_builder(EventBuilder::metadata(&metadata))
+            .await?;
+        self.send(relays, &event).await?;
+
+        let secret_key = self
+            .db
+            .find_user_private_key(channel_name)
+            .await?
+            .ok_or_else(|| Error::Custom("User private key not found".to_string()))?;
+        self.db
+            .set_keys(channel_name, &public_key.to_bech32()?, &secret_key)
+            .await?;
+        self.db.set_bunker(channel_name, Some(uri)).await?;
+        self.db.set_relay_list(channel_name, None).await?;
+        Ok(public_key)
+    }
+
+    /// Exports the key of `channel_name`, or of every channel, encrypted
+    /// with `passphrase`. Channels signing through a bunker have no key to
+    /// export and are skipped when exporting every channel.
+    pub async fn export_keys(
+        &mut self,
+        channel_name: Option<&str>,
+        passphrase: &str,
+    ) -> Result<Vec<KeyBackup>, Error> {
+        let channels = match channel_name {
+            Some(name) => vec![name.to_string()],
+            None => self.db.channels().await?,
+        };
+        let mut backups = Vec::new();
+        for channel in channels {
+            if self.db.find_user_bunker(&channel).await?.is_some() {
+                if channel_name.is_some() {
+                    return Err(Error::Custom(format!(
+                        "Channel {} signs through a bunker, which holds its key",
+                        channel
+                    )));
+                }
+                tracing::warn!(
+                    "Not exporting channel {}, which signs through a bunker",
+                    channel
+                );
+                continue;
+            }
+            let keys = self.local_keys(&channel).await?;
+            backups.push(KeyBackup {
+                ncryptsec: encrypt_key(&keys, passphrase)?,
+                channel,
+            });
+        }
+        Ok(backups)
+    }
+
+    /// Restores a key exported by [`App::export_keys`]. A channel without a
+    /// key is added with it, like [`App::add_channel`]; a channel that
+    /// already has it is left as is, and one with another key is an error.
+    /// Returns whether the channel was added.
+    pub async fn import_key(
+        &mut self,
+        backup: &KeyBackup,
+        passphrase: &str,
+        api: &str,
+        count: u32,
+    ) -> Result<bool, Error> {
+        let keys = decrypt_key(&backup.ncryptsec, passphrase)?;
+        if !self.db.user_exists(&backup.channel).await? {
+            self.create_user(&backup.channel, api, count, Some(keys))
                 .await?;
-            Ok(channel_id)
+            return Ok(true);
+        }
+        let current = self.local_keys(&backup.channel).await?;
+        if current.public_key() != keys.public_key() {
+            return Err(Error::Custom(format!(
+                "Channel {} has another key, use set-key to replace it",
+                backup.channel
+            )));
         }
+        Ok(false)
     }
 
+    /// Returns the videos of the channel's feed that were not seen before,
+    /// marking them as seen. On the channel's first poll only the videos
+    /// selected by `onboarding` are returned.
     pub async fn get_contents(
         &mut self,
         channel_id: &str,
         channel_name: &str,
-    ) -> Result<Vec<String>, Error> {
+        onboarding: OnboardingPolicy,
+    ) -> Result<Vec<VideoInfo>, Error> {
         let url = format!("https://rsshub.app/youtube/channel/{}", channel_id);
         let rss = RssFetcher::new(&url);
         let videos = rss.fetch().await?;
+        self.record_videos(channel_name, videos, onboarding).await
+    }
+
+    /// Marks the fetched `videos` of a channel as seen and returns those that
+    /// were not seen before, applying `onboarding` on the channel's first
+    /// poll.
+    pub async fn record_videos(
+        &mut self,
+        channel_name: &str,
+        videos: Vec<VideoInfo>,
+        onboarding: OnboardingPolicy,
+    ) -> Result<Vec<VideoInfo>, Error> {
         let mut ret = Vec::new();
 
         for video in videos {
             let video_exists = self.db.video_exists(&video.link).await?;
-            if !video_exists {
+            if !video_exists && self.dry_run {
+                if self.dry_run_seen.insert(video.link.clone()) {
+                    ret.push(video);
+                }
+            } else if !video_exists {
                 self.db
                     .add_video(
                         &video.author_name,
@@ -166,75 +617,287 @@ impl App {
                         false,
                     )
                     .await?;
-                let combined = format!("{}: {}", video.title, video.link);
-                ret.push(combined);
+                ret.push(video);
+            }
+        }
+
+        if !self.db.user_onboarded(channel_name).await? {
+            let found = ret.len();
+            ret = select_backfill(ret, onboarding);
+            tracing::info!(
+                "Onboarding user {} with policy {:?}: publishing {} of {} videos",
+                channel_name,
+                onboarding,
+                ret.len(),
+                found
+            );
+            if !self.dry_run {
+                self.db.set_onboarded(channel_name).await?;
             }
         }
         Ok(ret)
     }
 
+    /// Forgets that `videos` were seen, so the next poll queues them again.
+    /// Used to give back videos that were queued but not published.
+    pub async fn forget_videos(&mut self, videos: &[VideoInfo]) -> Result<(), Error> {
+        for video in videos {
+            if self.dry_run {
+                self.dry_run_seen.remove(&video.link);
+            } else {
+                self.db.remove_video(&video.link).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Signs `note` with the channel's key and sends it to `relays`, after
+    /// updating the channel's metadata. A failed metadata update does not
+    /// stop the note; it is returned in [`Published::metadata_error`]. The
+    /// video the note announces, if any, is then recorded as published.
     pub async fn publish(
         &mut self,
         channel_name: &str,
-        message: &str,
+        note: &Note,
         relays: &[String],
-    ) -> Result<(), Error> {
-        let secret_key = match self.db.find_user_private_key(channel_name).await {
-            Ok(Some(key)) => key,
-            Ok(None) => {
-                return Err(Error::Custom("User private key not found".to_string()));
-            }
-            Err(e) => {
-                return Err(Error::DbError(e));
-            }
+    ) -> Result<Published, Error> {
+        if self.dry_run {
+            let event_id = self
+                .print_dry_run(channel_name, nostr::text_note_builder(note))
+                .await?;
+            return Ok(Published {
+                event_id,
+                metadata_error: None,
+                relay_list_error: None,
+            });
+        }
+
+        let metadata = self.channel_metadata(channel_name).await?;
+        let signer = self.signer(channel_name).await?;
+        let metadata_error = self.send_metadata(&signer, &metadata, relays).await.err();
+        let relay_list_error = match self.relay_list_outdated(channel_name, relays).await {
+            Ok(true) => self
+                .send_relay_list(channel_name, &signer, relays)
+                .await
+                .err(),
+            Ok(false) => None,
+            Err(e) => Some(e),
         };
+        let event = signer
+            .sign_event_builder(nostr::text_note_builder(note))
+            .await;
+        close_signer(signer).await;
 
-        let avatar = match self.db.query_avatar(channel_name).await {
-            Ok(Some(avatar)) => avatar,
-            Ok(None) => {
-                return Err(Error::Custom("Avatar not found".to_string()));
+        let event_id = self.send(relays, &event?).await?;
+        if let Some(link) = &note.link {
+            // the note is out; failing here must not make it be sent again
+            if let Err(e) = self.db.set_published(link).await {
+                tracing::warn!(link = %link, error = %e, "Failed to record the note as published");
             }
-            Err(e) => {
-                return Err(Error::DbError(e));
-            }
-        };
+        }
+        Ok(Published {
+            event_id,
+            metadata_error,
+            relay_list_error,
+        })
+    }
 
-        let key = match self.convert_key(&secret_key) {
-            Ok(key) => key,
-            Err(e) => {
-                return Err(e);
+    /// Publishes the NIP-65 relay list of a channel's identity if `relays`
+    /// differ from the last list published, so that clients find its notes.
+    /// Returns whether a list was published. Channels without a key yet are
+    /// left alone, and nothing is published in dry-run mode.
+    pub async fn update_relay_list(
+        &mut self,
+        channel_name: &str,
+        relays: &[String],
+    ) -> Result<bool, Error> {
+        if self.dry_run || !self.db.user_exists(channel_name).await? {
+            return Ok(false);
+        }
+        if !self.relay_list_outdated(channel_name, relays).await? {
+            return Ok(false);
+        }
+        let signer = self.signer(channel_name).await?;
+        let sent = self.send_relay_list(channel_name, &signer, relays).await;
+        close_signer(signer).await;
+        sent.map(|()| true)
+    }
+
+    async fn relay_list_outdated(
+        &mut self,
+        channel_name: &str,
+        relays: &[String],
+    ) -> Result<bool, Error> {
+        let published = self.db.find_user_relay_list(channel_name).await?;
+        Ok(published.as_deref() != Some(relay_list_record(relays).as_str()))
+    }
+
+    /// Sends the relay list and records it once a relay has accepted it.
+    async fn send_relay_list(
+        &mut self,
+        channel_name: &str,
+        signer: &NostrSigner,
+        relays: &[String],
+    ) -> Result<(), Error> {
+        let event = signer
+            .sign_event_builder(nostr::relay_list_builder(relays))
+            .await?;
+        self.send(relays, &event).await?;
+        self.db
+            .set_relay_list(channel_name, Some(&relay_list_record(relays)))
+            .await?;
+        Ok(())
+    }
+
+    /// Sends the channel's profile metadata, signed by `signer`.
+    async fn send_metadata(
+        &mut self,
+        signer: &NostrSigner,
+        metadata: &Metadata,
+        relays: &[String],
+    ) -> Result<(), Error> {
+        let event = signer
+            .sign_event_builder(EventBuilder::metadata(metadata))
+            .await?;
+        self.send(relays, &event).await?;
+        Ok(())
+    }
+
+    /// Sends `event` to the relays of `relays` that are not demoted and
+    /// records how each of them answered. A relay is demoted after
+    /// [`DEMOTE_AFTER`] failures in a row and tried again once every
+    /// [`DEMOTED_RETRY`]; if every relay is demoted, all are tried.
+    async fn send(&mut self, relays: &[String], event: &Event) -> Result<EventId, Error> {
+        let relays = self.usable_relays(relays).await;
+        let sent = self.publisher.send_event(&relays, event).await;
+        let at = Utc::now().timestamp();
+        for report in self.publisher.take_reports() {
+            if let Err(e) = self
+                .db
+                .record_relay_send(&report.relay, &report.outcome, at)
+                .await
+            {
+                tracing::warn!(relay = %report.relay, error = %e, "Failed to record relay health");
             }
-        };
+        }
+        Ok(sent?)
+    }
 
-        let note_publish = match NotePublisher::new(&key, relays).await {
-            Ok(publisher) => publisher,
+    async fn usable_relays(&mut self, relays: &[String]) -> Vec<String> {
+        let health = match self.db.relay_health().await {
+            Ok(health) => health,
             Err(e) => {
-                return Err(Error::IO(e));
+                tracing::warn!(error = %e, "Failed to load relay health");
+                return relays.to_vec();
             }
         };
+        let now = Utc::now().timestamp();
+        let demoted: HashSet<&str> = health
+            .iter()
+            .filter(|relay| relay.is_demoted() && !relay.retry_due(now))
+            .map(|relay| relay.url.as_str())
+            .collect();
+        let usable: Vec<String> = relays
+            .iter()
+            .filter(|relay| !demoted.contains(relay.as_str()))
+            .cloned()
+            .collect();
+        if usable.is_empty() {
+            return relays.to_vec();
+        }
+        if usable.len() < relays.len() {
+            tracing::debug!(
+                skipped = relays.len() - usable.len(),
+                "Skipping demoted relays"
+            );
+        }
+        usable
+    }
 
-        let user_name = match self.db.query_user_name(channel_name).await {
-            Ok(Some(name)) => name,
-            Ok(None) => {
-                return Err(Error::Custom("User name not found".to_string()));
-            }
-            Err(e) => {
-                return Err(Error::DbError(e));
-            }
+    /// Fails if a channel other than `channel_name` publishes as `keys`, since
+    /// both would then share one identity, profile and relay list.
+    async fn check_key_unused(&mut self, channel_name: &str, keys: &Keys) -> Result<(), Error> {
+        let public_key = keys.public_key().to_bech32()?;
+        match self.db.find_channel_by_key(&public_key).await? {
+            Some(other) if other != channel_name => Err(Error::Custom(format!(
+                "Key is already used by channel {}",
+                other
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// The profile metadata of a channel's identity.
+    async fn channel_metadata(&mut self, channel_name: &str) -> Result<Metadata, Error> {
+        let user_name = match self.db.query_user_name(channel_name).await? {
+            Some(name) => name,
+            None => return Err(Error::Custom("User name not found".to_string())),
         };
+        let avatar = match self.db.query_avatar(channel_name).await? {
+            Some(avatar) => avatar,
+            None => return Err(Error::Custom("Avatar not found".to_string())),
+        };
+        let nip05 = self
+            .nip05_domain
+            .as_ref()
+            .map(|domain| format!("{}@{}", nostr::nip05_name(channel_name), domain));
+        Ok(nostr::metadata(&user_name, &avatar, nip05.as_deref())?)
+    }
 
-        note_publish.connect().await;
-        if let Err(e) = note_publish.set_metadata(&user_name, &avatar).await {
-            log::error!("Failed to set metadata: {}", e);
+    /// The key stored for a channel, or the throwaway key of a channel
+    /// added in dry-run mode.
+    async fn local_keys(&mut self, channel_name: &str) -> Result<Keys, Error> {
+        if let Some(keys) = self.dry_run_keys.get(channel_name) {
+            return Ok(keys.clone());
         }
+        match self.db.find_user_private_key(channel_name).await? {
+            Some(secret_key) => self.convert_key(&secret_key),
+            None => Err(Error::Custom("User private key not found".to_string())),
+        }
+    }
 
-        if let Err(e) = note_publish.publish_text_note(&key, message).await {
-            log::error!("Failed to publish text note: {}", e);
+    /// The identity of a channel: the public key its events are signed for.
+    async fn public_key(&mut self, channel_name: &str) -> Result<PublicKey, Error> {
+        if let Some(keys) = self.dry_run_keys.get(channel_name) {
+            return Ok(keys.public_key());
         }
-        time::sleep(Duration::from_secs(1)).await;
-        note_publish.disconnect().await;
+        match self.db.find_user_public_key(channel_name).await? {
+            Some(public_key) => Ok(PublicKey::from_bech32(&public_key)?),
+            None => Err(Error::Custom("User public key not found".to_string())),
+        }
+    }
 
-        Ok(())
+    /// Prints the event `builder` makes for a channel in dry-run mode and
+    /// returns its id. A channel signing through a bunker gets its event
+    /// printed unsigned, so that the bunker is not contacted.
+    async fn print_dry_run(
+        &mut self,
+        channel_name: &str,
+        builder: EventBuilder,
+    ) -> Result<EventId, Error> {
+        let bunker = !self.dry_run_keys.contains_key(channel_name)
+            && self.db.find_user_bunker(channel_name).await?.is_some();
+        if bunker {
+            let public_key = self.public_key(channel_name).await?;
+            return Ok(print_unsigned(builder.to_unsigned_event(public_key)));
+        }
+        let event = builder.to_event(&self.local_keys(channel_name).await?)?;
+        println!("{}", event.as_json());
+        Ok(event.id)
+    }
+
+    /// The signer of a channel: its own key, or its bunker if it was
+    /// connected to one with [`App::connect_bunker`]. A bunker is connected
+    /// for each use; close it with [`close_signer`].
+    async fn signer(&mut self, channel_name: &str) -> Result<NostrSigner, Error> {
+        let keys = self.local_keys(channel_name).await?;
+        if self.dry_run_keys.contains_key(channel_name) {
+            return Ok(NostrSigner::Keys(keys));
+        }
+        match self.db.find_user_bunker(channel_name).await? {
+            Some(uri) => Ok(NostrSigner::nip46(remote_signer(&uri, keys).await?)),
+            None => Ok(NostrSigner::Keys(keys)),
+        }
     }
 
     fn convert_key(&self, secret_key: &str) -> Result<Keys, Error> {
@@ -244,14 +907,159 @@ impl App {
     }
 }
 
+/// The relays of a relay list as stored: sorted, without duplicates, one
+/// per line.
+fn relay_list_record(relays: &[String]) -> String {
+    let mut relays: Vec<&str> = relays.iter().map(String::as_str).collect();
+    relays.sort_unstable();
+    relays.dedup();
+    relays.join("\n")
+}
+
+/// How long to wait for a bunker to connect and to answer each request.
+const BUNKER_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn bunker_uri(uri: &str) -> Result<NostrConnectURI, Error> {
+    NostrConnectURI::parse(uri).map_err(|e| Error::Custom(format!("Invalid bunker URI: {}", e)))
+}
+
+/// Connects to the bunker at `uri` as the client `keys`.
+async fn remote_signer(uri: &str, keys: Keys) -> Result<Nip46Signer, Error> {
+    Ok(Nip46Signer::new(bunker_uri(uri)?, keys, BUNKER_TIMEOUT, None).await?)
+}
+
+/// Prints an event with its id but without a signature, and returns the id.
+fn print_unsigned(mut event: UnsignedEvent) -> EventId {
+    let id = EventId::new(
+        &event.pubkey,
+        event.created_at,
+        &event.kind,
+        &event.tags,
+        &event.content,
+    );
+    event.id = Some(id);
+    println!("{}", event.as_json());
+    id
+}
+
+/// Disconnects a bunker signer; local keys need no closing.
+async fn close_signer(signer: NostrSigner) {
+    if let NostrSigner::NIP46(remote) = signer {
+        if let Err(e) = remote.shutdown().await {
+            tracing::warn!(error = %e, "Failed to disconnect from bunker");
+        }
+    }
+}
+
+fn select_backfill(mut videos: Vec<VideoInfo>, policy: OnboardingPolicy) -> Vec<VideoInfo> {
+    match policy {
+        OnboardingPolicy::All => videos,
+        OnboardingPolicy::Skip => Vec::new(),
+        OnboardingPolicy::Latest(n) => {
+            // newest first; videos without a date keep their feed order at the end
+            videos.sort_by_key(|video| std::cmp::Reverse(video.published));
+            videos.truncate(n);
+            videos
+        }
+        OnboardingPolicy::Since(since) => videos
+            .into_iter()
+            .filter(|video| video.published.is_some_and(|date| date >= since))
+            .collect(),
+    }
+}
+
+/// Outcome of [`App::publish`].
+#[derive(Debug)]
+pub struct Published {
+    pub event_id: EventId,
+    pub metadata_error: Option<Error>,
+    pub relay_list_error: Option<Error>,
+}
+
+/// A channel's key encrypted with NIP-49, as exported by
+/// [`App::export_keys`]. Written as one `<channel> <ncryptsec>` line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyBackup {
+    pub channel: String,
+    pub ncryptsec: String,
+}
+
+impl std::fmt::Display for KeyBackup {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.channel, self.ncryptsec)
+    }
+}
+
+impl FromStr for KeyBackup {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(channel), Some(ncryptsec), None) => Ok(Self {
+                channel: channel.to_string(),
+                ncryptsec: ncryptsec.to_string(),
+            }),
+            _ => Err(Error::Custom(format!(
+                "Expected `<channel> <ncryptsec>`, found `{}`",
+                line.trim()
+            ))),
+        }
+    }
+}
+
+/// Derives the key of the YouTube channel `channel_id` from a BIP-39
+/// `mnemonic`, along the NIP-06 path `m/44'/1237'/<account>'/0/0` with an
+/// account number taken from the channel id. The same mnemonic and channel
+/// always give the same key, so identities can be recreated from the
+/// mnemonic alone.
+pub fn derive_key(mnemonic: &str, channel_id: &str) -> Result<Keys, Error> {
+    let account = account_number(channel_id);
+    Ok(Keys::from_mnemonic_with_account(
+        mnemonic,
+        None,
+        Some(account),
+    )?)
+}
+
+/// The first 31 bits of the channel id's SHA-256, as hardened derivation
+/// only takes indexes below 2^31. Changing this changes every derived key.
+fn account_number(channel_id: &str) -> u32 {
+    let hash = sha256::Hash::hash(channel_id.as_bytes()).to_byte_array();
+    u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]) >> 1
+}
+
+/// scrypt work factor of exported keys, the one NIP-49 suggests.
+const KEY_LOG_N: u8 = 16;
+
+/// Encrypts the secret key of `keys` with `passphrase` as a NIP-49
+/// `ncryptsec`. The key is marked as weak, since the bot stores it in the
+/// clear.
+pub fn encrypt_key(keys: &Keys, passphrase: &str) -> Result<String, Error> {
+    let encrypted =
+        EncryptedSecretKey::new(keys.secret_key()?, passphrase, KEY_LOG_N, KeySecurity::Weak)?;
+    Ok(encrypted.to_bech32()?)
+}
+
+/// Decrypts a NIP-49 `ncryptsec` secret key with `passphrase`.
+pub fn decrypt_key(ncryptsec: &str, passphrase: &str) -> Result<Keys, Error> {
+    let encrypted = EncryptedSecretKey::from_bech32(ncryptsec.trim())?;
+    match encrypted.to_secret_key(passphrase) {
+        Ok(secret_key) => Ok(Keys::new(secret_key)),
+        Err(nip49::Error::ChaCha20Poly1305(_)) => Err(Error::Custom(
+            "Wrong passphrase or corrupted key".to_string(),
+        )),
+        Err(e) => Err(e.into()),
+    }
+}
+
 #[derive(Clone)]
 pub struct MyKey {
     pub public_key: String,
     pub secret_key: String,
 }
 
-fn new_key() -> Result<MyKey, Error> {
-    let my_keys: Keys = Keys::generate();
+fn my_key(my_keys: &Keys) -> Result<MyKey, Error> {
     let pk = my_keys.public_key().to_bech32()?;
     let prk = my_keys.secret_key()?.to_bech32()?;
     let ret = MyKey {
@@ -260,3 +1068,64 @@ fn new_key() -> Result<MyKey, Error> {
     };
     Ok(ret)
 }
+
+/// Parses a secret key given as `nsec` or hex.
+pub fn parse_secret_key(key: &str) -> Result<Keys, Error> {
+    Ok(Keys::parse(key.trim())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+    use std::io::ErrorKind;
+
+    fn io_error() -> std::io::Error {
+        std::io::Error::new(ErrorKind::ConnectionReset, "connection reset")
+    }
+
+    #[test]
+    fn failures_that_may_clear_up_are_transient() {
+        let transient = [
+            Error::from(api::RssError::Io(io_error())),
+            Error::from(api::YoutubeError::Io(io_error())),
+            Error::from(gpt::Error::ChatGPT(
+                chatgpt::err::Error::IOError(io_error()),
+            )),
+            Error::from(data::Error::IoError(io_error())),
+        ];
+        for e in transient {
+            assert!(e.is_transient(), "{}", e);
+        }
+
+        let permanent = [
+            Error::from(api::RssError::Rss(rss::Error::Eof)),
+            Error::from(api::YoutubeError::Custom("no such channel".to_string())),
+            Error::from(gpt::Error::ChatGPT(chatgpt::err::Error::ParsingError(
+                "bad reply".to_string(),
+            ))),
+            Error::from(data::Error::UserNotFound("channel".to_string())),
+        ];
+        for e in permanent {
+            assert!(!e.is_transient(), "{}", e);
+        }
+    }
+
+    #[test]
+    fn sources_lead_to_the_underlying_error() {
+        let e = Error::from(api::RssError::Io(io_error()));
+        let rss = e.source().unwrap();
+        assert!(rss.downcast_ref::<api::RssError>().is_some());
+        let io = rss.source().unwrap().downcast_ref::<std::io::Error>();
+        assert_eq!(
+            io.map(std::io::Error::kind),
+            Some(ErrorKind::ConnectionReset)
+        );
+
+        let e = Error::from(data::Error::UserNotFound("channel".to_string()));
+        let db = e.source().unwrap();
+        assert!(db.downcast_ref::<data::Error>().is_some());
+        assert!(db.source().is_none());
+        assert!(Error::Custom("message".to_string()).source().is_none());
+    }
+}
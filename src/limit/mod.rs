@@ -0,0 +1,281 @@
+use crate::conf::{PostingWindow, Publish, Rate};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A token bucket holding up to `burst` tokens and refilled with one token
+/// every `every` seconds. It starts full.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: Rate,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: Rate) -> Self {
+        Self {
+            rate,
+            tokens: rate.burst as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed / self.rate.every as f64).min(self.rate.burst as f64);
+        self.updated = now;
+    }
+
+    /// Time until a token is available, zero if there is one now.
+    pub fn wait_time(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) * self.rate.every as f64)
+        }
+    }
+
+    pub fn take(&mut self, now: Instant) {
+        self.refill(now);
+        self.tokens -= 1.0;
+    }
+
+    /// Returns a token taken for an event that was not published.
+    pub fn give_back(&mut self) {
+        self.tokens = (self.tokens + 1.0).min(self.rate.burst as f64);
+    }
+
+    /// Switches to `rate`, keeping the tokens already earned.
+    pub fn set_rate(&mut self, rate: Rate) {
+        self.refill(Instant::now());
+        self.rate = rate;
+        self.tokens = self.tokens.min(rate.burst as f64);
+    }
+}
+
+struct Identity {
+    bucket: TokenBucket,
+    day: NaiveDate,
+    posts: u32,
+}
+
+/// Decides when each identity may publish, combining the per-identity and
+/// global rate limits, quiet hours, posting windows and the daily cap.
+pub struct PostLimiter {
+    conf: Publish,
+    global: Option<TokenBucket>,
+    identities: HashMap<String, Identity>,
+}
+
+impl PostLimiter {
+    pub fn new(conf: Publish) -> Self {
+        Self {
+            global: conf.global_rate.map(TokenBucket::new),
+            conf,
+            identities: HashMap::new(),
+        }
+    }
+
+    pub fn set_config(&mut self, conf: Publish) {
+        match (&mut self.global, conf.global_rate) {
+            (Some(bucket), Some(rate)) => bucket.set_rate(rate),
+            (global, rate) => *global = rate.map(TokenBucket::new),
+        }
+        for identity in self.identities.values_mut() {
+            identity.bucket.set_rate(conf.identity_rate);
+        }
+        self.conf = conf;
+    }
+
+    /// Takes a slot for `identity` to publish a note if every limit allows
+    /// it now, otherwise returns how long to wait before asking again.
+    pub fn acquire(
+        &mut self,
+        identity: &str,
+        window: Option<PostingWindow>,
+    ) -> Result<(), Duration> {
+        let local = Utc::now().with_timezone(&self.conf.timezone);
+        self.acquire_at(identity, window, Instant::now(), local.naive_local())
+    }
+
+    /// [`PostLimiter::acquire`] at `now`, when the clock of the configured
+    /// time zone shows `local`.
+    fn acquire_at(
+        &mut self,
+        identity: &str,
+        window: Option<PostingWindow>,
+        now: Instant,
+        local: NaiveDateTime,
+    ) -> Result<(), Duration> {
+        let time = local.time();
+        let today = local.date();
+        let mut wait = Duration::ZERO;
+
+        if let Some(window) = window {
+            if !window.contains(time) {
+                wait = wait.max(time_until(window.start, time));
+            }
+        }
+        if let Some(quiet) = self.conf.quiet_hours {
+            if quiet.contains(time) {
+                wait = wait.max(time_until(quiet.end, time));
+            }
+        }
+
+        let rate = self.conf.identity_rate;
+        let state = self
+            .identities
+            .entry(identity.to_string())
+            .or_insert_with(|| Identity {
+                bucket: TokenBucket::new(rate),
+                day: today,
+                posts: 0,
+            });
+        if state.day != today {
+            state.day = today;
+            state.posts = 0;
+        }
+        if let Some(max) = self.conf.max_posts_per_day {
+            if state.posts >= max {
+                wait = wait.max(time_until(NaiveTime::MIN, time));
+            }
+        }
+        wait = wait.max(state.bucket.wait_time(now));
+        if let Some(global) = &mut self.global {
+            wait = wait.max(global.wait_time(now));
+        }
+
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        state.bucket.take(now);
+        state.posts += 1;
+        if let Some(global) = &mut self.global {
+            global.take(now);
+        }
+        Ok(())
+    }
+
+    /// Returns the slot taken by [`PostLimiter::acquire`] for a note that
+    /// failed to publish, so that failures do not count against the limits.
+    pub fn release(&mut self, identity: &str) {
+        if let Some(state) = self.identities.get_mut(identity) {
+            state.bucket.give_back();
+            state.posts = state.posts.saturating_sub(1);
+        }
+        if let Some(global) = &mut self.global {
+            global.give_back();
+        }
+    }
+
+    /// Forgets the state of an identity that is no longer configured.
+    pub fn remove(&mut self, identity: &str) {
+        self.identities.remove(identity);
+    }
+}
+
+/// Time from `now` until the clock next shows `target`.
+fn time_until(target: NaiveTime, now: NaiveTime) -> Duration {
+    let until = target.signed_duration_since(now);
+    match until.to_std() {
+        Ok(until) if !until.is_zero() => until,
+        _ => DAY.saturating_sub((-until).to_std().unwrap_or_default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_time(NaiveTime::parse_from_str(time, "%H:%M").unwrap())
+    }
+
+    fn window(window: &str) -> PostingWindow {
+        PostingWindow::try_from(window.to_string()).unwrap()
+    }
+
+    fn hours(hours: f64) -> Duration {
+        Duration::from_secs_f64(hours * 60.0 * 60.0)
+    }
+
+    /// A limiter whose rate limits never get in the way.
+    fn limiter(quiet_hours: Option<PostingWindow>) -> PostLimiter {
+        PostLimiter::new(Publish {
+            quiet_hours,
+            identity_rate: Rate {
+                burst: 100,
+                every: 1,
+            },
+            ..Publish::default()
+        })
+    }
+
+    #[test]
+    fn quiet_hours_across_midnight() {
+        let mut limiter = limiter(Some(window("22:00-06:00")));
+        let now = Instant::now();
+
+        assert_eq!(
+            limiter.acquire_at("a", None, now, at("23:00")),
+            Err(hours(7.0))
+        );
+        assert_eq!(
+            limiter.acquire_at("a", None, now, at("05:30")),
+            Err(hours(0.5))
+        );
+        assert_eq!(limiter.acquire_at("a", None, now, at("06:00")), Ok(()));
+        assert_eq!(limiter.acquire_at("a", None, now, at("21:59")), Ok(()));
+    }
+
+    #[test]
+    fn posting_window_across_midnight() {
+        let mut limiter = limiter(None);
+        let night = Some(window("23:00-01:00"));
+        let now = Instant::now();
+
+        assert_eq!(limiter.acquire_at("a", night, now, at("23:30")), Ok(()));
+        assert_eq!(limiter.acquire_at("a", night, now, at("00:30")), Ok(()));
+        assert_eq!(
+            limiter.acquire_at("a", night, now, at("01:00")),
+            Err(hours(22.0))
+        );
+    }
+
+    #[test]
+    fn window_with_equal_bounds_never_opens() {
+        // rejected by config validation for this reason
+        let mut limiter = limiter(None);
+        let empty = Some(window("08:00-08:00"));
+        let now = Instant::now();
+
+        for time in ["00:00", "08:00", "12:00"] {
+            assert!(limiter.acquire_at("a", empty, now, at(time)).is_err());
+        }
+    }
+
+    #[test]
+    fn released_slot_is_available_again() {
+        let mut limiter = PostLimiter::new(Publish {
+            max_posts_per_day: Some(1),
+            global_rate: Some(Rate {
+                burst: 1,
+                every: 60,
+            }),
+            ..Publish::default()
+        });
+        let now = Instant::now();
+
+        assert_eq!(limiter.acquire_at("a", None, now, at("12:00")), Ok(()));
+        assert!(limiter.acquire_at("a", None, now, at("12:00")).is_err());
+        limiter.release("a");
+        assert_eq!(limiter.acquire_at("a", None, now, at("12:00")), Ok(()));
+    }
+}
@@ -0,0 +1,94 @@
+use prometheus::{
+    register_histogram, register_int_counter_vec, register_int_gauge_vec, Encoder, Histogram,
+    IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use std::sync::OnceLock;
+
+/// Content type of [`gather`]'s output.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Cost in YouTube Data API quota units of a `search.list` request.
+pub const SEARCH_QUOTA: u64 = 100;
+/// Cost in YouTube Data API quota units of a `channels.list` request.
+pub const CHANNELS_QUOTA: u64 = 1;
+
+/// The bot's metrics, registered in the default Prometheus registry.
+pub struct Metrics {
+    /// Feed fetches by `source` (`rss`, `youtube`) and `outcome`.
+    pub feed_fetches: IntCounterVec,
+    /// New videos found per channel.
+    pub videos_discovered: IntCounterVec,
+    /// Notes sent per `relay` and `outcome`.
+    pub notes_published: IntCounterVec,
+    /// Time to publish one note to all relays.
+    pub publish_duration: Histogram,
+    /// YouTube Data API quota units used per `endpoint`.
+    pub youtube_quota: IntCounterVec,
+    /// Videos waiting to be published per channel.
+    pub queue_depth: IntGaugeVec,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(|| {
+        // make the database metrics show up before the first query
+        data::query_duration();
+        Metrics {
+            feed_fetches: register_int_counter_vec!(
+                "ytbot_feed_fetches_total",
+                "Feed fetches by source and outcome.",
+                &["source", "outcome"]
+            )
+            .expect("metric is registered once"),
+            videos_discovered: register_int_counter_vec!(
+                "ytbot_videos_discovered_total",
+                "New videos found in channel feeds.",
+                &["channel"]
+            )
+            .expect("metric is registered once"),
+            notes_published: register_int_counter_vec!(
+                "ytbot_notes_published_total",
+                "Notes sent to relays by relay and outcome.",
+                &["relay", "outcome"]
+            )
+            .expect("metric is registered once"),
+            publish_duration: register_histogram!(
+                "ytbot_publish_duration_seconds",
+                "Time to publish one note to all relays.",
+                vec![0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0]
+            )
+            .expect("metric is registered once"),
+            youtube_quota: register_int_counter_vec!(
+                "ytbot_youtube_quota_units_total",
+                "YouTube Data API quota units used.",
+                &["endpoint"]
+            )
+            .expect("metric is registered once"),
+            queue_depth: register_int_gauge_vec!(
+                "ytbot_queue_depth",
+                "Videos waiting to be published.",
+                &["channel"]
+            )
+            .expect("metric is registered once"),
+        }
+    })
+}
+
+/// Label value of an outcome.
+pub fn outcome<T, E>(res: &Result<T, E>) -> &'static str {
+    match res {
+        Ok(_) => "ok",
+        Err(_) => "error",
+    }
+}
+
+/// Renders every registered metric in the Prometheus text format.
+pub fn gather() -> Vec<u8> {
+    metrics();
+    let mut buf = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buf) {
+        tracing::error!("Failed to encode metrics: {}", e);
+    }
+    buf
+}
@@ -1,14 +1,54 @@
-use chrono::{Duration, Utc};
 use core::fmt;
 
+mod note;
+mod probe;
+pub use note::render_template;
+pub use note::Note;
+pub use probe::RelayProbe;
+
+use crate::metrics::{metrics, outcome};
+use data::RelayOutcome;
+use nostr_sdk::pool::relay;
 use nostr_sdk::types::url;
-use nostr_sdk::{Client, Keys, Metadata, ToBech32};
-use nostr_sdk::{EventBuilder, Url};
-use rand::Rng;
-use std::io::Result;
+use nostr_sdk::{Client, Event, EventId, Kind, Metadata, RelaySendOptions};
+use nostr_sdk::{EventBuilder, UncheckedUrl, Url};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+/// Sends signed events to relays. [`NotePublisher`] is the websocket
+/// implementation; tests can substitute their own.
+pub trait Publisher: Send + Sync {
+    /// Sends `event` to `relays` and returns its id if at least one relay
+    /// accepted it, [`Error::Refused`] if every relay refused it for good and
+    /// [`Error::Rejected`] otherwise.
+    fn send_event(
+        &self,
+        relays: &[String],
+        event: &Event,
+    ) -> impl Future<Output = Result<EventId, Error>> + Send;
+
+    /// How each relay answered the events sent since the last call. The
+    /// default publisher does not report.
+    fn take_reports(&self) -> Vec<RelayReport> {
+        Vec::new()
+    }
+}
+
+/// How one relay, as configured, answered one event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayReport {
+    pub relay: String,
+    pub outcome: RelayOutcome,
+}
 
+#[derive(Debug, Clone, Default)]
 pub struct NotePublisher {
-    client: Client,
+    timeout: Option<Duration>,
+    reports: Arc<Mutex<Vec<RelayReport>>>,
 }
 
 #[derive(Debug)]
@@ -17,6 +57,14 @@ pub enum Error {
     UrlParse(url::ParseError),
     Nip19(nostr_sdk::nips::nip19::Error),
     Client(nostr_sdk::client::Error),
+    Event(nostr_sdk::event::builder::Error),
+    /// None of the configured relays could be added.
+    NoRelays,
+    /// No relay accepted the event.
+    Rejected(EventId),
+    /// Every relay refused the event in a way a retry does not change, e.g.
+    /// `blocked:` or `invalid:`.
+    Refused(EventId),
 }
 
 impl From<nostr_sdk::nips::nip19::Error> for Error {
@@ -31,6 +79,12 @@ impl From<nostr_sdk::client::Error> for Error {
     }
 }
 
+impl From<nostr_sdk::event::builder::Error> for Error {
+    fn from(e: nostr_sdk::event::builder::Error) -> Self {
+        Self::Event(e)
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
         Self::Io(e)
@@ -50,77 +104,212 @@ impl fmt::Display for Error {
             Self::UrlParse(e) => write!(f, "UrlParse: {}", e),
             Self::Nip19(e) => write!(f, "nip19: {}", e),
             Self::Client(e) => write!(f, "Client: {}", e),
+            Self::Event(e) => write!(f, "Event: {}", e),
+            Self::NoRelays => write!(f, "no usable relay"),
+            Self::Rejected(id) => write!(f, "no relay accepted event {}", id),
+            Self::Refused(id) => write!(f, "every relay refused event {}", id),
         }
     }
 }
-impl NotePublisher {
-    pub async fn new(keys: &Keys, relays: &[String]) -> Result<Self> {
-        let client = Client::new(keys);
-        client
-            .add_relays(relays.to_vec())
-            .await
-            .expect("Failed to add relays");
 
-        Ok(Self { client })
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::UrlParse(e) => Some(e),
+            Self::Nip19(e) => Some(e),
+            Self::Client(e) => Some(e),
+            Self::Event(e) => Some(e),
+            Self::NoRelays | Self::Rejected(_) | Self::Refused(_) => None,
+        }
     }
+}
 
-    pub async fn connect(&self) {
-        self.client.connect().await;
+impl Error {
+    /// Relay and network failures, which may clear up on a later attempt.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Io(_) | Self::Client(_) | Self::Rejected(_))
     }
+}
 
-    pub async fn set_metadata(
-        &self,
-        username: &str,
-        avatar: &str,
-    ) -> std::result::Result<(), Error> {
-        let metadata = Metadata::new()
-            .name(username)
-            .display_name(username)
-            .about("Description")
-            .picture(Url::parse(avatar)?)
-            .banner(Url::parse(avatar)?)
-            .nip05("username@example.com")
-            .lud16("0")
-            .custom_field("custom_field", "value");
-
-        self.client.set_metadata(&metadata).await?;
-        Ok(())
-    }
-
-    pub async fn publish_text_note(
-        &self,
-        my_keys: &Keys,
-        message: &str,
-    ) -> std::result::Result<(), Error> {
-        let bech32_pubkey: String = my_keys.public_key().to_bech32()?;
-        log::info!("Bech32 PubKey: {}", bech32_pubkey);
-        //let time = custom_created_at();
-        let time = nostr_sdk::Timestamp::now();
+impl NotePublisher {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        let builder = EventBuilder::text_note(message, []).custom_created_at(time);
-        self.client.send_event_builder(builder).await?;
+    /// How long to wait for each relay to acknowledge an event. Defaults to
+    /// the client's send timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
 
-        Ok(())
+    fn report(&self, relay: &str, outcome: RelayOutcome) {
+        self.reports
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(RelayReport {
+                relay: relay.to_string(),
+                outcome,
+            });
     }
+}
+
+impl Publisher for NotePublisher {
+    /// Connects to `relays` and sends `event` to every relay separately, so
+    /// one failing relay does not affect the others. A relay that cannot be
+    /// added is skipped and reported as failed.
+    async fn send_event(&self, relays: &[String], event: &Event) -> Result<EventId, Error> {
+        let client = Client::default();
+        // reports name relays as configured, not as normalized by the client
+        let mut configured = HashMap::new();
+        for relay in relays {
+            match client.add_relay(relay.as_str()).await {
+                Ok(_) => {
+                    if let Ok(url) = Url::parse(relay) {
+                        configured.insert(url, relay.clone());
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(relay = %relay, error = %e, "Skipping relay");
+                    self.report(relay, RelayOutcome::Failed(e.to_string()));
+                }
+            }
+        }
+        if configured.is_empty() {
+            return Err(Error::NoRelays);
+        }
+        client.connect().await;
 
-    pub async fn disconnect(&self) {
-        match self.client.disconnect().await {
-            Ok(_) => (),
-            Err(e) => log::error!("Failed to disconnect: {}", e),
+        let event_id = event.id;
+        let opts = RelaySendOptions::new().timeout(self.timeout);
+        let mut sends = JoinSet::new();
+        for (url, relay) in client.relays().await {
+            let event = event.clone();
+            sends.spawn(async move {
+                let start = Instant::now();
+                let res = relay.send_event(event, opts).await;
+                (url, res, start.elapsed())
+            });
         }
+        let sent = sends.len();
+        let (mut accepted, mut refused) = (0, 0);
+        while let Some(joined) = sends.join_next().await {
+            let (url, res, latency) = match joined {
+                Ok(sent) => sent,
+                Err(e) => {
+                    tracing::error!(error = %e, "Relay send task failed");
+                    continue;
+                }
+            };
+            // metadata updates are not counted as notes
+            if event.kind == Kind::TextNote {
+                metrics()
+                    .notes_published
+                    .with_label_values(&[url.as_str(), outcome(&res)])
+                    .inc();
+            }
+            let relay = configured
+                .get(&url)
+                .cloned()
+                .unwrap_or_else(|| url.to_string());
+            match res {
+                Ok(_) => {
+                    tracing::debug!(relay = %url, event_id = %event_id, "Relay accepted event");
+                    accepted += 1;
+                    self.report(&relay, RelayOutcome::Accepted(latency));
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        relay = %url,
+                        event_id = %event_id,
+                        error = %e,
+                        "Relay did not accept event"
+                    );
+                    let outcome = relay_outcome(e);
+                    if matches!(&outcome, RelayOutcome::Rejected(message) if is_refusal(message)) {
+                        refused += 1;
+                    }
+                    self.report(&relay, outcome);
+                }
+            }
+        }
+        if let Err(e) = client.disconnect().await {
+            tracing::error!("Failed to disconnect: {}", e);
+        }
+        if accepted == 0 && refused == sent {
+            return Err(Error::Refused(event_id));
+        }
+        if accepted == 0 {
+            return Err(Error::Rejected(event_id));
+        }
+        tracing::info!(event_id = %event_id, relays = accepted, "Event accepted");
+        Ok(event_id)
+    }
+
+    fn take_reports(&self) -> Vec<RelayReport> {
+        std::mem::take(&mut *self.reports.lock().unwrap_or_else(PoisonError::into_inner))
     }
 }
 
-pub fn custom_created_at() -> nostr_sdk::Timestamp {
-    let now = Utc::now();
-    let mut rng = rand::thread_rng();
-    let minutes_to_subtract: i64 = rng.gen_range(0..30);
-    let new_time = now - Duration::minutes(minutes_to_subtract);
+/// A relay answering `OK false` rejected the event; any other error means
+/// it could not be reached or did not answer.
+fn relay_outcome(e: relay::Error) -> RelayOutcome {
+    match e {
+        relay::Error::EventNotPublished(message) => RelayOutcome::Rejected(message),
+        e => RelayOutcome::Failed(e.to_string()),
+    }
+}
+
+/// Whether a relay's `OK false` message says the event will never be
+/// accepted as it is. NIP-01 prefixes other refusals with e.g.
+/// `rate-limited:` or `error:`, which may clear up.
+fn is_refusal(message: &str) -> bool {
+    ["blocked:", "invalid:", "pow:"]
+        .iter()
+        .any(|prefix| message.starts_with(prefix))
+}
+
+/// Profile metadata of a channel's identity, with its NIP-05 identifier if
+/// the bot serves one.
+pub fn metadata(username: &str, avatar: &str, nip05: Option<&str>) -> Result<Metadata, Error> {
+    let metadata = Metadata::new()
+        .name(username)
+        .display_name(username)
+        .about("Description")
+        .picture(Url::parse(avatar)?)
+        .banner(Url::parse(avatar)?)
+        .lud16("0")
+        .custom_field("custom_field", "value");
+    match nip05 {
+        Some(nip05) => Ok(metadata.nip05(nip05)),
+        None => Ok(metadata),
+    }
+}
 
-    // Convert new_time to a Unix timestamp
-    let unix_timestamp: u64 = new_time.timestamp() as u64;
+/// The NIP-05 name of a channel: its name in lowercase, with the characters
+/// NIP-05 does not allow in names replaced by `-`.
+pub fn nip05_name(channel: &str) -> String {
+    channel
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9' | '-' | '_' | '.') => c,
+            _ => '-',
+        })
+        .collect()
+}
 
-    // Convert unix_timestamp to nostr_sdk::Timestamp
+/// Builds the kind-1 event for `note`.
+pub fn text_note_builder(note: &Note) -> EventBuilder {
+    EventBuilder::text_note(&note.content, note.tags.clone()).custom_created_at(note.created_at)
+}
 
-    nostr_sdk::Timestamp::from(unix_timestamp)
+/// Builds the NIP-65 relay list (kind 10002) of an identity publishing to
+/// `relays`, listing each for both reading and writing.
+pub fn relay_list_builder(relays: &[String]) -> EventBuilder {
+    EventBuilder::relay_list(
+        relays
+            .iter()
+            .map(|relay| (UncheckedUrl::from(relay.as_str()), None)),
+    )
 }
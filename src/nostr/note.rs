@@ -0,0 +1,83 @@
+use crate::api::VideoInfo;
+use crate::conf::{ChannelSettings, CreatedAtPolicy};
+use chrono::{DateTime, Utc};
+use nostr_sdk::{Tag, Timestamp};
+
+/// Label namespace of the language tags, see NIP-32.
+const LANGUAGE_NAMESPACE: &str = "ISO-639-1";
+
+/// The content and tags of a text note, ready to be signed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub content: String,
+    pub tags: Vec<Tag>,
+    pub created_at: Timestamp,
+    /// Link of the video the note announces, recorded as published once a
+    /// relay accepted the note.
+    pub link: Option<String>,
+}
+
+impl Note {
+    /// Builds the note announcing `video` from `content`, appending the
+    /// channel's hashtags to it and tagging it with the hashtags and
+    /// language. The video's publish time is used by
+    /// [`CreatedAtPolicy::Published`].
+    pub fn new(content: String, video: &VideoInfo, settings: &ChannelSettings) -> Self {
+        let mut content = content;
+        let mut tags = Vec::new();
+
+        if !settings.hashtags.is_empty() {
+            let hashtags: Vec<String> = settings
+                .hashtags
+                .iter()
+                .map(|tag| format!("#{}", tag))
+                .collect();
+            content = format!("{}\n\n{}", content, hashtags.join(" "));
+            tags.extend(
+                settings
+                    .hashtags
+                    .iter()
+                    .map(|tag| Tag::Hashtag(tag.to_lowercase())),
+            );
+        }
+
+        if let Some(language) = &settings.language {
+            tags.push(Tag::LabelNamespace(LANGUAGE_NAMESPACE.to_string()));
+            tags.push(Tag::Label(vec![
+                language.clone(),
+                LANGUAGE_NAMESPACE.to_string(),
+            ]));
+        }
+
+        Self {
+            content,
+            tags,
+            created_at: created_at(video.published, settings),
+            link: Some(video.link.clone()),
+        }
+    }
+}
+
+fn created_at(published: Option<DateTime<Utc>>, settings: &ChannelSettings) -> Timestamp {
+    let now = Utc::now();
+    let time = match (settings.created_at, published) {
+        (CreatedAtPolicy::Published, Some(published)) => {
+            let earliest = i64::try_from(settings.max_backdate.as_secs())
+                .ok()
+                .and_then(chrono::Duration::try_seconds)
+                .and_then(|max_backdate| now.checked_sub_signed(max_backdate))
+                .unwrap_or(DateTime::<Utc>::MIN_UTC);
+            published.clamp(earliest, now)
+        }
+        _ => now,
+    };
+    Timestamp::from(time.timestamp() as u64)
+}
+
+/// Fills the `{title}`, `{link}` and `{author}` placeholders of `template`.
+pub fn render_template(template: &str, video: &VideoInfo) -> String {
+    template
+        .replace("{title}", &video.title)
+        .replace("{link}", &video.link)
+        .replace("{author}", &video.author_name)
+}
@@ -0,0 +1,60 @@
+use nostr_sdk::{Client, RelayStatus, Url};
+use std::collections::HashSet;
+
+/// Keeps a connection open to every configured relay, so readiness can be
+/// judged without waiting for the next publish.
+pub struct RelayProbe {
+    client: Client,
+}
+
+impl RelayProbe {
+    pub fn new() -> Self {
+        Self {
+            client: Client::default(),
+        }
+    }
+
+    /// Connects to the relays in `relays` and drops the others.
+    pub async fn set_relays(&self, relays: &HashSet<String>) {
+        let wanted: HashSet<Url> = relays
+            .iter()
+            .filter_map(|relay| match Url::parse(relay) {
+                Ok(url) => Some(url),
+                Err(e) => {
+                    tracing::warn!("Not probing relay {}: {}", relay, e);
+                    None
+                }
+            })
+            .collect();
+        for url in self.client.relays().await.into_keys() {
+            if !wanted.contains(&url) {
+                if let Err(e) = self.client.remove_relay(url.clone()).await {
+                    tracing::warn!("Failed to stop probing relay {}: {}", url, e);
+                }
+            }
+        }
+        for url in wanted {
+            if let Err(e) = self.client.add_relay(url.clone()).await {
+                tracing::warn!("Failed to probe relay {}: {}", url, e);
+            }
+        }
+        self.client.connect().await;
+    }
+
+    /// Number of relays currently connected.
+    pub async fn connected(&self) -> usize {
+        let mut connected = 0;
+        for relay in self.client.relays().await.into_values() {
+            if relay.status().await == RelayStatus::Connected {
+                connected += 1;
+            }
+        }
+        connected
+    }
+}
+
+impl Default for RelayProbe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
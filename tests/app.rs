@@ -0,0 +1,291 @@
+mod common;
+
+use chrono::{DateTime, TimeZone, Utc};
+use common::{add_user, test_dir, CHANNEL, CHANNEL_ID};
+use nostr_sdk::{Event, EventId, Keys, Timestamp};
+use std::time::Duration;
+use youtube_bot::{
+    App, DbConnection, MemoryStore, NostrError, Note, OnboardingPolicy, Publisher, RelayOutcome,
+    Store, VideoInfo,
+};
+
+fn video(n: u32) -> VideoInfo {
+    VideoInfo {
+        title: format!("Video {}", n),
+        link: format!("https://www.youtube.com/watch?v=video{}", n),
+        author_name: "Author".to_string(),
+        published: Some(day(n)),
+    }
+}
+
+fn day(n: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, n, 12, 0, 0).unwrap()
+}
+
+fn links(videos: &[VideoInfo]) -> Vec<&str> {
+    videos.iter().map(|video| video.link.as_str()).collect()
+}
+
+async fn with_user<S: Store>(mut store: S) -> App<S> {
+    add_user(&mut store, CHANNEL, &Keys::generate()).await;
+    App::with_store(store)
+}
+
+fn sqlite() -> DbConnection {
+    let mut db = DbConnection::new("sqlite://:memory:").unwrap();
+    db.run_migrations().unwrap();
+    db
+}
+
+async fn check_known_user<S: Store>(store: S) {
+    let mut app = with_user(store).await;
+    let channel_id = app.check_user(CHANNEL, "unused", 1).await.unwrap();
+    assert_eq!(channel_id, CHANNEL_ID);
+}
+
+async fn new_videos_are_returned_once<S: Store>(store: S) {
+    let mut app = with_user(store).await;
+    let first = app
+        .record_videos(CHANNEL, vec![video(1), video(2)], OnboardingPolicy::All)
+        .await
+        .unwrap();
+    assert_eq!(links(&first), links(&[video(1), video(2)]));
+
+    let second = app
+        .record_videos(
+            CHANNEL,
+            vec![video(1), video(2), video(3)],
+            OnboardingPolicy::All,
+        )
+        .await
+        .unwrap();
+    assert_eq!(links(&second), links(&[video(3)]));
+}
+
+async fn onboarding_applies_to_first_poll<S: Store>(store: S) {
+    let mut app = with_user(store).await;
+    let first = app
+        .record_videos(
+            CHANNEL,
+            vec![video(1), video(2), video(3)],
+            OnboardingPolicy::Latest(1),
+        )
+        .await
+        .unwrap();
+    assert_eq!(links(&first), links(&[video(3)]));
+
+    let second = app
+        .record_videos(
+            CHANNEL,
+            vec![video(4), video(5)],
+            OnboardingPolicy::Latest(1),
+        )
+        .await
+        .unwrap();
+    assert_eq!(links(&second), links(&[video(4), video(5)]));
+}
+
+async fn forgotten_videos_are_returned_again<S: Store>(store: S) {
+    let mut app = with_user(store).await;
+    let videos = app
+        .record_videos(CHANNEL, vec![video(1), video(2)], OnboardingPolicy::All)
+        .await
+        .unwrap();
+    app.forget_videos(&videos[1..]).await.unwrap();
+
+    let again = app
+        .record_videos(CHANNEL, vec![video(1), video(2)], OnboardingPolicy::All)
+        .await
+        .unwrap();
+    assert_eq!(links(&again), links(&[video(2)]));
+}
+
+async fn unknown_channel_fails<S: Store>(store: S) {
+    let mut app = with_user(store).await;
+    let result = app
+        .record_videos("other", vec![video(1)], OnboardingPolicy::All)
+        .await;
+    assert!(result.is_err());
+}
+
+async fn relay_health_is_accumulated<S: Store>(mut store: S) {
+    let relay = "wss://relay.example.com";
+    let accepted = RelayOutcome::Accepted(Duration::from_millis(100));
+    let failed = RelayOutcome::Failed("connection refused".to_string());
+    store.record_relay_send(relay, &accepted, 1).await.unwrap();
+    store.record_relay_send(relay, &failed, 2).await.unwrap();
+    store
+        .record_relay_send("wss://other.example.com", &accepted, 3)
+        .await
+        .unwrap();
+
+    let health = store.relay_health().await.unwrap();
+    assert_eq!(health.len(), 2);
+    let relay = health.iter().find(|r| r.url == relay).unwrap();
+    assert_eq!((relay.sent, relay.accepted, relay.failed), (2, 1, 1));
+    assert_eq!(relay.consecutive_failures, 1);
+    assert_eq!(relay.acceptance_rate(), Some(0.5));
+    assert_eq!(relay.average_latency(), Some(Duration::from_millis(100)));
+    assert_eq!(relay.last_error.as_deref(), Some("connection refused"));
+    assert_eq!(relay.last_attempt, 2);
+}
+
+/// Accepts every event without sending it.
+struct AcceptAll;
+
+impl Publisher for AcceptAll {
+    async fn send_event(&self, _relays: &[String], event: &Event) -> Result<EventId, NostrError> {
+        Ok(event.id)
+    }
+}
+
+async fn published_video_is_recorded<S: Store>(store: S) {
+    let mut app = with_user(store).await.with_publisher(AcceptAll);
+    let videos = app
+        .record_videos(CHANNEL, vec![video(1), video(2)], OnboardingPolicy::All)
+        .await
+        .unwrap();
+    assert_eq!(links(&videos), links(&[video(1), video(2)]));
+
+    let note = Note {
+        content: "Video 1".to_string(),
+        tags: Vec::new(),
+        created_at: Timestamp::now(),
+        link: Some(video(1).link),
+    };
+    app.publish(CHANNEL, &note, &["wss://unused".to_string()])
+        .await
+        .unwrap();
+
+    let mut store = app.into_store();
+    assert!(store.video_published(&video(1).link).await.unwrap());
+    assert!(!store.video_published(&video(2).link).await.unwrap());
+}
+
+#[tokio::test]
+async fn memory_check_known_user() {
+    check_known_user(MemoryStore::new()).await;
+}
+
+#[tokio::test]
+async fn memory_new_videos_are_returned_once() {
+    new_videos_are_returned_once(MemoryStore::new()).await;
+}
+
+#[tokio::test]
+async fn memory_onboarding_applies_to_first_poll() {
+    onboarding_applies_to_first_poll(MemoryStore::new()).await;
+}
+
+#[tokio::test]
+async fn memory_forgotten_videos_are_returned_again() {
+    forgotten_videos_are_returned_again(MemoryStore::new()).await;
+}
+
+#[tokio::test]
+async fn memory_unknown_channel_fails() {
+    unknown_channel_fails(MemoryStore::new()).await;
+}
+
+#[tokio::test]
+async fn memory_relay_health_is_accumulated() {
+    relay_health_is_accumulated(MemoryStore::new()).await;
+}
+
+#[tokio::test]
+async fn memory_published_video_is_recorded() {
+    published_video_is_recorded(MemoryStore::new()).await;
+}
+
+#[tokio::test]
+async fn sqlite_check_known_user() {
+    check_known_user(sqlite()).await;
+}
+
+#[tokio::test]
+async fn sqlite_new_videos_are_returned_once() {
+    new_videos_are_returned_once(sqlite()).await;
+}
+
+#[tokio::test]
+async fn sqlite_onboarding_applies_to_first_poll() {
+    onboarding_applies_to_first_poll(sqlite()).await;
+}
+
+#[tokio::test]
+async fn sqlite_forgotten_videos_are_returned_again() {
+    forgotten_videos_are_returned_again(sqlite()).await;
+}
+
+#[tokio::test]
+async fn sqlite_unknown_channel_fails() {
+    unknown_channel_fails(sqlite()).await;
+}
+
+#[tokio::test]
+async fn sqlite_relay_health_is_accumulated() {
+    relay_health_is_accumulated(sqlite()).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_relay_sends_all_count() {
+    let dir = test_dir("relay-health");
+    let dsn = format!("sqlite://{}", dir.join("ytbot.db").display());
+    DbConnection::new(&dsn).unwrap().run_migrations().unwrap();
+
+    // one connection per worker, as the bot has
+    let relay = "wss://relay.example.com";
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let mut db = DbConnection::new(&dsn).unwrap();
+            tokio::spawn(async move {
+                let accepted = RelayOutcome::Accepted(Duration::from_millis(10));
+                let failed = RelayOutcome::Failed("connection refused".to_string());
+                for n in 0..25 {
+                    let outcome = if n % 5 == 0 { &failed } else { &accepted };
+                    db.record_relay_send(relay, outcome, n).await.unwrap();
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.await.unwrap();
+    }
+
+    let health = DbConnection::new(&dsn)
+        .unwrap()
+        .relay_health()
+        .await
+        .unwrap();
+    assert_eq!(health.len(), 1);
+    let health = &health[0];
+    assert_eq!((health.sent, health.accepted, health.failed), (100, 80, 20));
+    assert_eq!(health.average_latency(), Some(Duration::from_millis(10)));
+    assert_eq!(health.last_error.as_deref(), Some("connection refused"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn sqlite_published_video_is_recorded() {
+    published_video_is_recorded(sqlite()).await;
+}
+
+#[tokio::test]
+async fn dry_run_does_not_write() {
+    let mut store = MemoryStore::new();
+    let mut app = with_user(store.clone()).await.dry_run(true);
+    let first = app
+        .record_videos(CHANNEL, vec![video(1)], OnboardingPolicy::All)
+        .await
+        .unwrap();
+    assert_eq!(links(&first), links(&[video(1)]));
+    let second = app
+        .record_videos(CHANNEL, vec![video(1)], OnboardingPolicy::All)
+        .await
+        .unwrap();
+    assert!(second.is_empty());
+
+    assert!(store.videos(CHANNEL).is_empty());
+    assert!(!store.user_onboarded(CHANNEL).await.unwrap());
+}
@@ -0,0 +1,180 @@
+mod common;
+mod mock_relay;
+
+use common::{add_user, note, CHANNEL};
+use mock_relay::MockRelay;
+use nostr_sdk::nips::nip46::Request;
+use nostr_sdk::signer::nip46::{NostrConnectRemoteSigner, NostrConnectSignerActions};
+use nostr_sdk::{Keys, Kind, ToBech32};
+use std::future::Future;
+use youtube_bot::{App, MemoryStore, Store};
+
+/// Answers the bot's requests, signing only if `sign` is set.
+struct Owner {
+    sign: bool,
+}
+
+impl NostrConnectSignerActions for Owner {
+    fn approve(&self, req: &Request) -> bool {
+        match req {
+            Request::SignEvent(_) => self.sign,
+            _ => true,
+        }
+    }
+}
+
+/// Runs `test` with the bunker URI of a stand-in bunker holding `owner`'s
+/// key and answering through `relay`.
+async fn with_bunker<F>(owner: &Keys, sign: bool, relay: &MockRelay, test: impl FnOnce(String) -> F)
+where
+    F: Future<Output = ()>,
+{
+    let bunker = NostrConnectRemoteSigner::new(
+        owner.secret_key().unwrap().clone(),
+        [relay.url()],
+        Some("secret".to_string()),
+        None,
+    )
+    .await
+    .unwrap();
+    let uri = bunker.nostr_connect_uri().await.to_string();
+    tokio::select! {
+        served = bunker.serve(Box::new(Owner { sign })) => panic!("bunker stopped: {:?}", served),
+        () = async {
+            relay.wait_for_subscriptions(1).await;
+            test(uri).await
+        } => (),
+    }
+}
+
+#[tokio::test]
+async fn connected_channel_publishes_as_the_owner() {
+    let relay = MockRelay::start().await;
+    let owner = Keys::generate();
+    let client = Keys::generate();
+    let mut store = MemoryStore::new();
+    add_user(&mut store, CHANNEL, &client).await;
+    let mut app = App::with_store(store.clone());
+
+    let (mock, owner_key, inspect) = (relay.clone(), owner.public_key(), store.clone());
+    with_bunker(&owner, true, &relay, |uri| async move {
+        let (relay, mut store) = (mock, inspect);
+        let identity = app
+            .connect_bunker(CHANNEL, &uri, &[relay.url()])
+            .await
+            .unwrap();
+        assert_eq!(identity, owner_key);
+        assert_eq!(store.find_user_bunker(CHANNEL).await.unwrap(), Some(uri));
+
+        let published = app.publish(CHANNEL, &note(), &[relay.url()]).await.unwrap();
+        let stored = relay.stored();
+        assert!(stored.iter().all(|event| event.pubkey == owner_key));
+        let note = stored
+            .iter()
+            .find(|event| event.id == published.event_id)
+            .unwrap();
+        assert_eq!(note.kind, Kind::TextNote);
+    })
+    .await;
+
+    // the bot only keeps its client key
+    let secret_key = store.find_user_private_key(CHANNEL).await.unwrap();
+    assert_eq!(
+        secret_key,
+        Some(client.secret_key().unwrap().to_bech32().unwrap())
+    );
+}
+
+#[tokio::test]
+async fn bunker_refusing_to_sign_leaves_the_channel_unchanged() {
+    let relay = MockRelay::start().await;
+    let client = Keys::generate();
+    let mut store = MemoryStore::new();
+    add_user(&mut store, CHANNEL, &client).await;
+    let mut app = App::with_store(store.clone());
+
+    let mock = relay.clone();
+    with_bunker(&Keys::generate(), false, &relay, |uri| async move {
+        let relay = mock;
+        assert!(app
+            .connect_bunker(CHANNEL, &uri, &[relay.url()])
+            .await
+            .is_err());
+        assert_eq!(store.find_user_bunker(CHANNEL).await.unwrap(), None);
+
+        let published = app.publish(CHANNEL, &note(), &[relay.url()]).await.unwrap();
+        let note = relay
+            .stored()
+            .into_iter()
+            .find(|event| event.id == published.event_id)
+            .unwrap();
+        assert_eq!(note.pubkey, client.public_key());
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn new_key_replaces_the_bunker() {
+    let relay = MockRelay::start().await;
+    let owner = Keys::generate();
+    let new = Keys::generate();
+    let mut store = MemoryStore::new();
+    add_user(&mut store, CHANNEL, &Keys::generate()).await;
+    let mut app = App::with_store(store.clone());
+
+    let (mock, owner_key) = (relay.clone(), owner.public_key());
+    with_bunker(&owner, true, &relay, |uri| async move {
+        let relay = mock;
+        app.connect_bunker(CHANNEL, &uri, &[relay.url()])
+            .await
+            .unwrap();
+        app.replace_key(CHANNEL, &new, &[relay.url()])
+            .await
+            .unwrap();
+        assert_eq!(store.find_user_bunker(CHANNEL).await.unwrap(), None);
+
+        // the old identity, signed through the bunker, points to the new one
+        let moved = relay.stored().pop().unwrap();
+        assert_eq!(moved.pubkey, owner_key);
+        assert!(moved
+            .content
+            .contains(&new.public_key().to_bech32().unwrap()));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn dry_run_does_not_contact_the_bunker() {
+    let relay = MockRelay::start().await;
+    let owner = Keys::generate();
+    let mut store = MemoryStore::new();
+    add_user(&mut store, CHANNEL, &Keys::generate()).await;
+    // nothing answers on this relay
+    let uri = format!(
+        "bunker://{}?relay=ws://127.0.0.1:9",
+        owner.public_key().to_hex()
+    );
+    let mut app = App::with_store(store.clone()).dry_run(true);
+
+    let identity = app
+        .connect_bunker(CHANNEL, &uri, &[relay.url()])
+        .await
+        .unwrap();
+    assert_eq!(identity, owner.public_key());
+    assert_eq!(store.find_user_bunker(CHANNEL).await.unwrap(), None);
+
+    store.set_bunker(CHANNEL, Some(&uri)).await.unwrap();
+    store
+        .set_keys(
+            CHANNEL,
+            &owner.public_key().to_bech32().unwrap(),
+            &Keys::generate().secret_key().unwrap().to_bech32().unwrap(),
+        )
+        .await
+        .unwrap();
+    app.publish(CHANNEL, &note(), &[relay.url()]).await.unwrap();
+    app.replace_key(CHANNEL, &Keys::generate(), &[relay.url()])
+        .await
+        .unwrap();
+    assert!(relay.received().is_empty());
+}
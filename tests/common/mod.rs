@@ -0,0 +1,72 @@
+//! Fixtures shared by the test crates: channels with keys, a note to
+//! publish, a directory for files and an HTTP client.
+
+// each test crate uses only part of the fixtures
+#![allow(dead_code)]
+
+use nostr_sdk::{Keys, Timestamp, ToBech32};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use youtube_bot::{App, MemoryStore, Note, Store};
+
+pub const CHANNEL: &str = "channel";
+pub const CHANNEL_ID: &str = "UC0123456789";
+
+/// Adds `channel` to `store` with `keys` as its key.
+pub async fn add_user<S: Store>(store: &mut S, channel: &str, keys: &Keys) {
+    store
+        .add_user(
+            "Author",
+            "https://example.com/avatar.jpg",
+            &keys.public_key().to_bech32().unwrap(),
+            &keys.secret_key().unwrap().to_bech32().unwrap(),
+            channel,
+            CHANNEL_ID,
+        )
+        .await
+        .unwrap();
+}
+
+/// A store holding [`CHANNEL`] with `keys` as its key.
+pub async fn store_with_user(keys: &Keys) -> MemoryStore {
+    let mut store = MemoryStore::new();
+    add_user(&mut store, CHANNEL, keys).await;
+    store
+}
+
+pub async fn app_with_user(keys: &Keys) -> App<MemoryStore> {
+    App::with_store(store_with_user(keys).await)
+}
+
+pub fn note() -> Note {
+    Note {
+        content: "New video".to_string(),
+        tags: Vec::new(),
+        created_at: Timestamp::now(),
+        link: None,
+    }
+}
+
+/// A directory for the files of one test, removed first if it exists.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ytbot-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Sends a GET request and returns the response head and body.
+pub async fn get(addr: SocketAddr, path: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.to_lowercase(), body.to_string())
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use common::{get, test_dir};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::watch;
+use youtube_bot::{Config, HttpServer, StatusBoard};
+
+/// Starts a server for a channel publishing to a relay nothing listens on.
+/// Returns its address and the sender of its config, which has to be kept.
+async fn start(dir: &Path, dry_run: bool) -> (SocketAddr, watch::Sender<Arc<Config>>) {
+    let conf: Config = serde_yaml::from_str(&format!(
+        "
+youtube: {{api_key: key, user_id: [channel], count: 5}}
+nostr: {{relays: ['ws://127.0.0.1:1']}}
+postgres: {{dsn: 'sqlite://{}'}}
+",
+        dir.join("ytbot.db").display()
+    ))
+    .unwrap();
+    let (updates, receiver) = watch::channel(Arc::new(conf));
+    let server = HttpServer::bind(
+        "127.0.0.1:0".parse().unwrap(),
+        Arc::new(StatusBoard::default()),
+        receiver,
+    )
+    .await
+    .unwrap()
+    .dry_run(dry_run);
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+    (addr, updates)
+}
+
+#[tokio::test]
+async fn readiness_needs_a_connected_relay() {
+    let dir = test_dir("readyz");
+    let (addr, _updates) = start(&dir, false).await;
+
+    let (head, body) = get(addr, "/readyz").await;
+    assert!(head.starts_with("http/1.1 503"));
+    let readiness: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(readiness["database"], true);
+    assert_eq!(readiness["relays_connected"], 0);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn dry_run_is_ready_without_relays() {
+    let dir = test_dir("readyz-dry-run");
+    let (addr, _updates) = start(&dir, true).await;
+
+    let (head, body) = get(addr, "/readyz").await;
+    assert!(head.starts_with("http/1.1 200"));
+    let readiness: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(readiness["ready"], true);
+    assert!(readiness.get("relays_connected").is_none());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,213 @@
+mod common;
+mod mock_relay;
+
+use common::{add_user, app_with_user, note, CHANNEL};
+use mock_relay::{MockRelay, Reply};
+use nostr_sdk::{JsonUtil, Keys, Kind, Metadata, ToBech32};
+use youtube_bot::{
+    decrypt_key, derive_key, encrypt_key, parse_secret_key, App, KeyBackup, MemoryStore, Store,
+};
+
+#[test]
+fn secret_key_is_parsed_from_nsec_and_hex() {
+    let keys = Keys::generate();
+    let secret_key = keys.secret_key().unwrap();
+
+    let from_nsec = parse_secret_key(&secret_key.to_bech32().unwrap()).unwrap();
+    let from_hex = parse_secret_key(&format!(" {} ", secret_key.display_secret())).unwrap();
+
+    assert_eq!(from_nsec.public_key(), keys.public_key());
+    assert_eq!(from_hex.public_key(), keys.public_key());
+    assert!(parse_secret_key("nsec1invalid").is_err());
+    assert!(parse_secret_key(&keys.public_key().to_bech32().unwrap()).is_err());
+}
+
+/// The NIP-06 test mnemonic.
+const MNEMONIC: &str =
+    "leader monkey parrot ring guide accident before fence cannon height naive bean";
+
+#[test]
+fn derived_keys_depend_on_mnemonic_and_channel() {
+    let key = derive_key(MNEMONIC, "UC0123456789").unwrap();
+
+    // identities recreated from the mnemonic must not change
+    assert_eq!(
+        key.public_key().to_bech32().unwrap(),
+        "npub1zum7n09dx9dzthkprzqnm559h896p00vyf4mah2rew8hdxdrcmhs5t6u05"
+    );
+    let again = derive_key(MNEMONIC, "UC0123456789").unwrap();
+    assert_eq!(again.public_key(), key.public_key());
+    let other_channel = derive_key(MNEMONIC, "UC9876543210").unwrap();
+    assert_ne!(other_channel.public_key(), key.public_key());
+    assert!(derive_key("not a mnemonic", "UC0123456789").is_err());
+}
+
+#[tokio::test]
+async fn replaced_key_signs_metadata_and_later_notes() {
+    let relay = MockRelay::start().await;
+    let old = Keys::generate();
+    let new = Keys::generate();
+    let mut app = app_with_user(&old).await;
+
+    app.replace_key(CHANNEL, &new, &[relay.url()])
+        .await
+        .unwrap();
+    let published = app.publish(CHANNEL, &note(), &[relay.url()]).await.unwrap();
+
+    let stored = relay.stored();
+    let profile = &stored[0];
+    assert_eq!(profile.kind, Kind::Metadata);
+    assert_eq!(profile.pubkey, new.public_key());
+    assert_eq!(
+        Metadata::from_json(&profile.content)
+            .unwrap()
+            .name
+            .as_deref(),
+        Some("Author")
+    );
+
+    let moved = &stored[1];
+    assert_eq!(moved.kind, Kind::Metadata);
+    assert_eq!(moved.pubkey, old.public_key());
+    let about = Metadata::from_json(&moved.content).unwrap().about.unwrap();
+    assert!(about.contains(&new.public_key().to_bech32().unwrap()));
+
+    let note = stored
+        .iter()
+        .find(|event| event.id == published.event_id)
+        .unwrap();
+    assert_eq!(note.pubkey, new.public_key());
+}
+
+#[tokio::test]
+async fn key_is_kept_when_no_relay_takes_the_new_profile() {
+    let relay = MockRelay::start().await;
+    relay.reply_with(Reply::Reject("blocked: not allowed".to_string()));
+    let old = Keys::generate();
+    let mut app = app_with_user(&old).await;
+
+    assert!(app
+        .replace_key(CHANNEL, &Keys::generate(), &[relay.url()])
+        .await
+        .is_err());
+
+    relay.reply_with(Reply::Accept);
+    let published = app.publish(CHANNEL, &note(), &[relay.url()]).await.unwrap();
+    let note = relay
+        .stored()
+        .into_iter()
+        .find(|event| event.id == published.event_id)
+        .unwrap();
+    assert_eq!(note.pubkey, old.public_key());
+}
+
+#[tokio::test]
+async fn replacing_with_the_same_key_fails() {
+    let relay = MockRelay::start().await;
+    let keys = Keys::generate();
+    let mut app = app_with_user(&keys).await;
+
+    assert!(app
+        .replace_key(CHANNEL, &keys, &[relay.url()])
+        .await
+        .is_err());
+    assert!(app
+        .replace_key("other", &Keys::generate(), &[relay.url()])
+        .await
+        .is_err());
+    assert!(relay.received().is_empty());
+}
+
+#[tokio::test]
+async fn key_of_another_channel_is_refused() {
+    let relay = MockRelay::start().await;
+    let keys = Keys::generate();
+    let mut store = MemoryStore::new();
+    add_user(&mut store, CHANNEL, &keys).await;
+    add_user(&mut store, "other", &Keys::generate()).await;
+    let mut app = App::with_store(store);
+
+    assert!(app
+        .add_channel("new", "unused", 1, Some(keys.clone()))
+        .await
+        .is_err());
+    assert!(app
+        .replace_key("other", &keys, &[relay.url()])
+        .await
+        .is_err());
+    assert!(relay.received().is_empty());
+}
+
+#[test]
+fn encrypted_key_needs_the_passphrase() {
+    let keys = Keys::generate();
+    let ncryptsec = encrypt_key(&keys, "passphrase").unwrap();
+
+    assert!(ncryptsec.starts_with("ncryptsec1"));
+    let decrypted = decrypt_key(&ncryptsec, "passphrase").unwrap();
+    assert_eq!(decrypted.public_key(), keys.public_key());
+    assert!(decrypt_key(&ncryptsec, "wrong").is_err());
+}
+
+#[test]
+fn key_backup_is_one_line_per_channel() {
+    let backup = KeyBackup {
+        channel: CHANNEL.to_string(),
+        ncryptsec: "ncryptsec1abc".to_string(),
+    };
+
+    assert_eq!(backup.to_string(), "channel ncryptsec1abc");
+    assert_eq!(backup.to_string().parse::<KeyBackup>().unwrap(), backup);
+    assert!("channel".parse::<KeyBackup>().is_err());
+    assert!("channel ncryptsec1abc extra".parse::<KeyBackup>().is_err());
+}
+
+#[tokio::test]
+async fn exported_keys_are_imported_back() {
+    let keys = Keys::generate();
+    let other = Keys::generate();
+    let mut store = MemoryStore::new();
+    add_user(&mut store, CHANNEL, &keys).await;
+    add_user(&mut store, "connected", &Keys::generate()).await;
+    add_user(&mut store, "other", &other).await;
+    store
+        .set_bunker("connected", Some("bunker://unused"))
+        .await
+        .unwrap();
+    let mut app = App::with_store(store);
+
+    // channels signing through a bunker have no key to export
+    let backups = app.export_keys(None, "passphrase").await.unwrap();
+    let channels: Vec<&str> = backups.iter().map(|b| b.channel.as_str()).collect();
+    assert_eq!(channels, vec![CHANNEL, "other"]);
+    assert!(app
+        .export_keys(Some("connected"), "passphrase")
+        .await
+        .is_err());
+    assert_eq!(
+        decrypt_key(&backups[1].ncryptsec, "passphrase")
+            .unwrap()
+            .public_key(),
+        other.public_key()
+    );
+
+    let added = app
+        .import_key(&backups[0], "passphrase", "unused", 1)
+        .await
+        .unwrap();
+    assert!(!added);
+    assert!(app
+        .import_key(&backups[0], "wrong", "unused", 1)
+        .await
+        .is_err());
+
+    // a key for a channel that has another one is not imported
+    let moved = KeyBackup {
+        channel: "other".to_string(),
+        ncryptsec: backups[0].ncryptsec.clone(),
+    };
+    assert!(app
+        .import_key(&moved, "passphrase", "unused", 1)
+        .await
+        .is_err());
+}
@@ -0,0 +1,199 @@
+//! An in-process relay speaking enough of NIP-01 for tests: it verifies and
+//! stores the events it receives, answers `EVENT` with `OK`, serves stored
+//! events to `REQ` and forwards new events to open subscriptions.
+
+// each test crate uses only part of the relay
+#![allow(dead_code)]
+
+use futures_util::{SinkExt, StreamExt};
+use nostr_sdk::{ClientMessage, Event, Filter, JsonUtil, RelayMessage, SubscriptionId};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+/// How the relay answers `EVENT` messages.
+#[derive(Debug, Clone)]
+pub enum Reply {
+    /// Store the event and acknowledge it.
+    Accept,
+    /// Answer `OK false` with the message.
+    Reject(String),
+    /// Never answer, so the sender times out.
+    Ignore,
+}
+
+struct State {
+    reply: Reply,
+    received: Vec<Event>,
+    stored: Vec<Event>,
+    /// Subscriptions open on all connections.
+    subscriptions: usize,
+    /// Accepted events, for the open subscriptions of every connection.
+    live: broadcast::Sender<Event>,
+}
+
+#[derive(Clone)]
+pub struct MockRelay {
+    url: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockRelay {
+    /// Starts a relay on a free local port that accepts every valid event.
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay = Self {
+            url: format!("ws://{}", listener.local_addr().unwrap()),
+            state: Arc::new(Mutex::new(State {
+                reply: Reply::Accept,
+                received: Vec::new(),
+                stored: Vec::new(),
+                subscriptions: 0,
+                live: broadcast::channel(64).0,
+            })),
+        };
+        let state = relay.state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, state.clone()));
+            }
+        });
+        relay
+    }
+
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    pub fn reply_with(&self, reply: Reply) {
+        lock(&self.state).reply = reply;
+    }
+
+    /// Every event received, whether it was accepted or not.
+    pub fn received(&self) -> Vec<Event> {
+        lock(&self.state).received.clone()
+    }
+
+    /// The events accepted and stored; ephemeral events are only forwarded.
+    pub fn stored(&self) -> Vec<Event> {
+        lock(&self.state).stored.clone()
+    }
+
+    /// Waits until at least `n` subscriptions are open, so that events sent
+    /// afterwards reach the subscribers.
+    pub async fn wait_for_subscriptions(&self, n: usize) {
+        while lock(&self.state).subscriptions < n {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+async fn serve(stream: TcpStream, state: Arc<Mutex<State>>) {
+    let ws = match tokio_tungstenite::accept_async(stream).await {
+        Ok(ws) => ws,
+        Err(_) => return,
+    };
+    let mut subscriptions = HashMap::new();
+    session(ws, &state, &mut subscriptions).await;
+    lock(&state).subscriptions -= subscriptions.len();
+}
+
+async fn session(
+    ws: WebSocketStream<TcpStream>,
+    state: &Mutex<State>,
+    subscriptions: &mut HashMap<SubscriptionId, Vec<Filter>>,
+) {
+    let (mut tx, mut rx) = ws.split();
+    let mut live = lock(state).live.subscribe();
+    loop {
+        let replies = tokio::select! {
+            msg = rx.next() => match msg {
+                Some(Ok(Message::Text(text))) => handle(&text, state, subscriptions),
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => continue,
+            },
+            event = live.recv() => match event {
+                Ok(event) => forward(&event, subscriptions),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+        };
+        for reply in replies {
+            if tx.send(Message::Text(reply.as_json())).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn forward(
+    event: &Event,
+    subscriptions: &HashMap<SubscriptionId, Vec<Filter>>,
+) -> Vec<RelayMessage> {
+    subscriptions
+        .iter()
+        .filter(|(_, filters)| filters.iter().any(|filter| filter.match_event(event)))
+        .map(|(id, _)| RelayMessage::event(id.clone(), event.clone()))
+        .collect()
+}
+
+fn handle(
+    text: &str,
+    state: &Mutex<State>,
+    subscriptions: &mut HashMap<SubscriptionId, Vec<Filter>>,
+) -> Vec<RelayMessage> {
+    match ClientMessage::from_json(text) {
+        Ok(ClientMessage::Event(event)) => {
+            let event = *event;
+            let id = event.id;
+            if event.verify().is_err() {
+                return vec![RelayMessage::ok(id, false, "invalid: bad signature")];
+            }
+            let mut state = lock(state);
+            state.received.push(event.clone());
+            match state.reply.clone() {
+                Reply::Accept => {
+                    if !event.kind.is_ephemeral() {
+                        state.stored.push(event.clone());
+                    }
+                    let _ = state.live.send(event);
+                    vec![RelayMessage::ok(id, true, "")]
+                }
+                Reply::Reject(message) => vec![RelayMessage::ok(id, false, message)],
+                Reply::Ignore => Vec::new(),
+            }
+        }
+        Ok(ClientMessage::Req {
+            subscription_id,
+            filters,
+        }) => {
+            let mut state = lock(state);
+            let mut replies: Vec<RelayMessage> = state
+                .stored
+                .iter()
+                .filter(|event| filters.iter().any(|filter| filter.match_event(event)))
+                .map(|event| RelayMessage::event(subscription_id.clone(), event.clone()))
+                .collect();
+            replies.push(RelayMessage::eose(subscription_id.clone()));
+            if subscriptions.insert(subscription_id, filters).is_none() {
+                state.subscriptions += 1;
+            }
+            replies
+        }
+        Ok(ClientMessage::Close(subscription_id)) => {
+            if subscriptions.remove(&subscription_id).is_some() {
+                lock(state).subscriptions -= 1;
+            }
+            Vec::new()
+        }
+        Ok(_) => Vec::new(),
+        Err(e) => vec![RelayMessage::notice(format!("error: {}", e))],
+    }
+}
@@ -0,0 +1,100 @@
+mod common;
+mod mock_relay;
+
+use common::{add_user, get, note, test_dir};
+use mock_relay::MockRelay;
+use nostr_sdk::{JsonUtil, Keys, Kind, Metadata};
+use std::sync::Arc;
+use tokio::sync::watch;
+use youtube_bot::{load_conf, nip05_name, App, DbConnection, HttpServer, MemoryStore, StatusBoard};
+
+const CHANNEL: &str = "My_Channel";
+
+#[test]
+fn names_are_lowercase_with_allowed_characters() {
+    assert_eq!(nip05_name("My_Channel"), "my_channel");
+    assert_eq!(nip05_name("news.daily-2"), "news.daily-2");
+    assert_eq!(nip05_name("Café Tokyo"), "caf--tokyo");
+}
+
+#[tokio::test]
+async fn profile_claims_the_channel_name_at_the_domain() {
+    let relay = MockRelay::start().await;
+    let mut store = MemoryStore::new();
+    add_user(&mut store, CHANNEL, &Keys::generate()).await;
+    add_user(&mut store, "unverified", &Keys::generate()).await;
+    let mut app = App::with_store(store.clone()).nip05_domain(Some("example.org".to_string()));
+    let mut without = App::with_store(store);
+
+    app.publish(CHANNEL, &note(), &[relay.url()]).await.unwrap();
+    without
+        .publish("unverified", &note(), &[relay.url()])
+        .await
+        .unwrap();
+
+    let profiles: Vec<Metadata> = relay
+        .stored()
+        .iter()
+        .filter(|event| event.kind == Kind::Metadata)
+        .map(|event| Metadata::from_json(&event.content).unwrap())
+        .collect();
+    assert_eq!(profiles[0].nip05.as_deref(), Some("my_channel@example.org"));
+    assert_eq!(profiles[1].nip05, None);
+}
+
+#[tokio::test]
+async fn server_maps_names_to_keys_and_relays() {
+    let dir = test_dir("nip05");
+    let dsn = format!("sqlite://{}", dir.join("ytbot.db").display());
+    let mut db = DbConnection::new(&dsn).unwrap();
+    db.run_migrations().unwrap();
+    let keys = Keys::generate();
+    add_user(&mut db, CHANNEL, &keys).await;
+    add_user(&mut db, "removed", &Keys::generate()).await;
+
+    let config = dir.join("config.yaml");
+    std::fs::write(
+        &config,
+        format!(
+            "youtube:\n  api_key: key\n  count: 5\n  user_id:\n    - {}\n\
+             nostr:\n  relays: [\"ws://127.0.0.1:1\"]\n  nip05_domain: example.org\n\
+             postgres:\n  dsn: {}\n",
+            CHANNEL, dsn
+        ),
+    )
+    .unwrap();
+    let conf = load_conf(&config.to_string_lossy()).unwrap();
+    let (_updates, receiver) = watch::channel(Arc::new(conf));
+    let server = HttpServer::bind(
+        "127.0.0.1:0".parse().unwrap(),
+        Arc::new(StatusBoard::default()),
+        receiver,
+    )
+    .await
+    .unwrap();
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+
+    let (head, body) = get(addr, "/.well-known/nostr.json").await;
+    assert!(head.starts_with("http/1.1 200"));
+    assert!(head.contains("access-control-allow-origin: *"));
+    let document: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let hex = keys.public_key().to_hex();
+    // channels no longer configured are not listed
+    assert_eq!(document["names"], serde_json::json!({ "my_channel": hex }));
+    assert_eq!(
+        document["relays"][&hex],
+        serde_json::json!(["ws://127.0.0.1:1"])
+    );
+
+    // clients may percent-encode the name
+    let (_, body) = get(addr, "/.well-known/nostr.json?name=My%5Fchannel").await;
+    let document: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(document["names"], serde_json::json!({ "my_channel": hex }));
+
+    let (_, body) = get(addr, "/.well-known/nostr.json?name=other").await;
+    let document: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(document["names"], serde_json::json!({}));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
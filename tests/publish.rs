@@ -0,0 +1,289 @@
+mod common;
+mod mock_relay;
+
+use common::{app_with_user, note, store_with_user, CHANNEL};
+use mock_relay::{MockRelay, Reply};
+use nostr_sdk::{Client, Event, EventId, Filter, Keys, Kind, Tag, Timestamp};
+use std::time::Duration;
+use tokio::time::Instant;
+use youtube_bot::{
+    App, MemoryStore, NostrError, NotePublisher, Publisher, RelayOutcome, Store, DEMOTED_RETRY,
+    DEMOTE_AFTER,
+};
+
+fn kinds(events: &[Event]) -> Vec<Kind> {
+    events.iter().map(|event| event.kind).collect()
+}
+
+/// The relays of a NIP-65 relay list, sorted.
+fn relay_urls(event: &Event) -> Vec<String> {
+    let mut urls: Vec<String> = event
+        .tags
+        .iter()
+        .filter_map(|tag| match tag {
+            Tag::RelayMetadata(url, None) => Some(url.to_string()),
+            _ => None,
+        })
+        .collect();
+    urls.sort();
+    urls
+}
+
+#[tokio::test]
+async fn publishes_signed_metadata_relay_list_and_note() {
+    let relay = MockRelay::start().await;
+    let keys = Keys::generate();
+    let mut app = app_with_user(&keys).await;
+
+    let published = app.publish(CHANNEL, &note(), &[relay.url()]).await.unwrap();
+    assert!(published.metadata_error.is_none());
+
+    let stored = relay.stored();
+    assert_eq!(
+        kinds(&stored),
+        vec![Kind::Metadata, Kind::RelayList, Kind::TextNote]
+    );
+    assert!(stored.iter().all(|event| event.pubkey == keys.public_key()));
+    assert!(stored[0].content.contains("\"name\":\"Author\""));
+    assert_eq!(relay_urls(&stored[1]), vec![relay.url()]);
+    assert_eq!(stored[2].id, published.event_id);
+    assert_eq!(stored[2].content, "New video");
+}
+
+#[tokio::test]
+async fn one_rejecting_relay_does_not_fail_the_note() {
+    let accepting = MockRelay::start().await;
+    let rejecting = MockRelay::start().await;
+    rejecting.reply_with(Reply::Reject("blocked: not allowed".to_string()));
+    let mut app = app_with_user(&Keys::generate()).await;
+
+    let published = app
+        .publish(CHANNEL, &note(), &[accepting.url(), rejecting.url()])
+        .await
+        .unwrap();
+
+    assert_eq!(accepting.stored()[2].id, published.event_id);
+    assert_eq!(rejecting.received()[2].id, published.event_id);
+    assert!(rejecting.stored().is_empty());
+}
+
+#[tokio::test]
+async fn note_rejected_by_every_relay_fails() {
+    let relay = MockRelay::start().await;
+    relay.reply_with(Reply::Reject("rate-limited: slow down".to_string()));
+    let mut app = app_with_user(&Keys::generate()).await;
+
+    let err = app
+        .publish(CHANNEL, &note(), &[relay.url()])
+        .await
+        .unwrap_err();
+
+    assert!(err.is_transient());
+    assert_eq!(
+        kinds(&relay.received()),
+        vec![Kind::Metadata, Kind::RelayList, Kind::TextNote]
+    );
+}
+
+#[tokio::test]
+async fn note_refused_by_every_relay_is_not_retried() {
+    let invalid = MockRelay::start().await;
+    invalid.reply_with(Reply::Reject("invalid: bad tags".to_string()));
+    let refusing = MockRelay::start().await;
+    refusing.reply_with(Reply::Reject("blocked: not allowed".to_string()));
+    let mut app = app_with_user(&Keys::generate()).await;
+
+    let err = app
+        .publish(CHANNEL, &note(), &[invalid.url(), refusing.url()])
+        .await
+        .unwrap_err();
+
+    assert!(matches!(
+        err,
+        youtube_bot::Error::NostrWrapper(NostrError::Refused(_))
+    ));
+    assert!(!err.is_transient());
+}
+
+#[tokio::test]
+async fn unacknowledged_note_times_out() {
+    let relay = MockRelay::start().await;
+    relay.reply_with(Reply::Ignore);
+    let mut app = app_with_user(&Keys::generate())
+        .await
+        .with_publisher(NotePublisher::new().timeout(Duration::from_millis(200)));
+
+    let start = Instant::now();
+    let result = app.publish(CHANNEL, &note(), &[relay.url()]).await;
+
+    assert!(result.is_err());
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(relay.received().len(), 3);
+}
+
+#[tokio::test]
+async fn published_note_is_served_to_subscribers() {
+    let relay = MockRelay::start().await;
+    let keys = Keys::generate();
+    let mut app = app_with_user(&keys).await;
+    let published = app.publish(CHANNEL, &note(), &[relay.url()]).await.unwrap();
+
+    let client = Client::default();
+    client.add_relay(relay.url()).await.unwrap();
+    client.connect().await;
+    let filter = Filter::new().author(keys.public_key()).kind(Kind::TextNote);
+    let events = client
+        .get_events_of(vec![filter], Some(Duration::from_secs(5)))
+        .await
+        .unwrap();
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].id, published.event_id);
+}
+
+/// Accepts notes without sending them and fails metadata updates.
+struct FailingMetadata;
+
+impl Publisher for FailingMetadata {
+    async fn send_event(&self, _relays: &[String], event: &Event) -> Result<EventId, NostrError> {
+        match event.kind {
+            Kind::Metadata => Err(NostrError::Rejected(event.id)),
+            _ => Ok(event.id),
+        }
+    }
+}
+
+#[tokio::test]
+async fn failed_metadata_does_not_stop_the_note() {
+    let mut app = app_with_user(&Keys::generate())
+        .await
+        .with_publisher(FailingMetadata);
+
+    let published = app
+        .publish(CHANNEL, &note(), &["wss://unused".to_string()])
+        .await
+        .unwrap();
+
+    assert!(published.metadata_error.is_some());
+}
+
+#[tokio::test]
+async fn relay_list_is_published_again_when_relays_change() {
+    let relay = MockRelay::start().await;
+    let added = MockRelay::start().await;
+    let mut app = app_with_user(&Keys::generate()).await;
+
+    app.publish(CHANNEL, &note(), &[relay.url()]).await.unwrap();
+    assert!(!app
+        .update_relay_list(CHANNEL, &[relay.url()])
+        .await
+        .unwrap());
+    app.publish(CHANNEL, &note(), &[relay.url()]).await.unwrap();
+    let relays = [relay.url(), added.url()];
+    assert!(app.update_relay_list(CHANNEL, &relays).await.unwrap());
+    assert!(!app.update_relay_list(CHANNEL, &relays).await.unwrap());
+
+    let lists: Vec<Event> = relay
+        .stored()
+        .into_iter()
+        .filter(|event| event.kind == Kind::RelayList)
+        .collect();
+    assert_eq!(lists.len(), 2);
+    assert_eq!(relay_urls(&lists[0]), vec![relay.url()]);
+    let mut expected = relays.to_vec();
+    expected.sort();
+    assert_eq!(relay_urls(&lists[1]), expected);
+    assert_eq!(kinds(&added.stored()), vec![Kind::RelayList]);
+}
+
+#[tokio::test]
+async fn rejected_relay_list_is_sent_again() {
+    let relay = MockRelay::start().await;
+    relay.reply_with(Reply::Reject("blocked: not allowed".to_string()));
+    let mut app = app_with_user(&Keys::generate()).await;
+
+    assert!(app
+        .update_relay_list(CHANNEL, &[relay.url()])
+        .await
+        .is_err());
+    relay.reply_with(Reply::Accept);
+    assert!(app
+        .update_relay_list(CHANNEL, &[relay.url()])
+        .await
+        .unwrap());
+    assert!(!app
+        .update_relay_list("other", &[relay.url()])
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn relay_answers_are_recorded() {
+    let accepting = MockRelay::start().await;
+    let rejecting = MockRelay::start().await;
+    rejecting.reply_with(Reply::Reject("blocked: not allowed".to_string()));
+    let mut store = store_with_user(&Keys::generate()).await;
+    let mut app = App::with_store(store.clone());
+
+    app.publish(CHANNEL, &note(), &[accepting.url(), rejecting.url()])
+        .await
+        .unwrap();
+
+    let health = store.relay_health().await.unwrap();
+    let accepted = health.iter().find(|r| r.url == accepting.url()).unwrap();
+    assert_eq!((accepted.sent, accepted.accepted), (3, 3));
+    assert_eq!(accepted.consecutive_failures, 0);
+    assert!(accepted.average_latency().is_some());
+    let rejected = health.iter().find(|r| r.url == rejecting.url()).unwrap();
+    assert_eq!((rejected.sent, rejected.rejected), (3, 3));
+    assert_eq!(rejected.consecutive_failures, 3);
+    assert_eq!(rejected.last_error.as_deref(), Some("blocked: not allowed"));
+}
+
+/// Records `DEMOTE_AFTER` failures of `relay`, the last at `at`.
+async fn demote(store: &mut MemoryStore, relay: &str, at: i64) {
+    let outcome = RelayOutcome::Failed("connection refused".to_string());
+    for _ in 0..DEMOTE_AFTER {
+        store.record_relay_send(relay, &outcome, at).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn demoted_relay_is_skipped_unless_no_other_is_left() {
+    let healthy = MockRelay::start().await;
+    let failing = MockRelay::start().await;
+    let mut store = store_with_user(&Keys::generate()).await;
+    demote(&mut store, &failing.url(), Timestamp::now().as_u64() as i64).await;
+    let mut app = App::with_store(store.clone());
+
+    app.publish(CHANNEL, &note(), &[healthy.url(), failing.url()])
+        .await
+        .unwrap();
+    assert_eq!(healthy.stored().len(), 3);
+    assert!(failing.received().is_empty());
+
+    // a relay that accepts again is promoted
+    app.publish(CHANNEL, &note(), &[failing.url()])
+        .await
+        .unwrap();
+    assert!(!failing.received().is_empty());
+    let health = store.relay_health().await.unwrap();
+    let failing = health.iter().find(|r| r.url == failing.url()).unwrap();
+    assert!(!failing.is_demoted());
+}
+
+#[tokio::test]
+async fn demoted_relay_is_tried_again_later() {
+    let healthy = MockRelay::start().await;
+    let failing = MockRelay::start().await;
+    let mut store = store_with_user(&Keys::generate()).await;
+    let long_ago = Timestamp::now().as_u64() as i64 - DEMOTED_RETRY.as_secs() as i64;
+    demote(&mut store, &failing.url(), long_ago).await;
+    let mut app = App::with_store(store);
+
+    app.publish(CHANNEL, &note(), &[healthy.url(), failing.url()])
+        .await
+        .unwrap();
+
+    assert_eq!(failing.stored().len(), 3);
+}